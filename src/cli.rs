@@ -0,0 +1,129 @@
+use redox_damage::emu::io::ppu::Renderer;
+use redox_damage::emu::mapper::MapperKind;
+use redox_damage::emu::model::Model;
+
+const DEFAULT_ROM_PATH: &str = "rom.gb";
+const DEFAULT_HEADLESS_FRAMES: u32 = 600;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run,
+    // print the cartridge header and exit
+    Info,
+    // run without a window for a fixed number of frames
+    Headless,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub rom_path: String,
+    pub mapper: Option<MapperKind>,
+    pub patch_path: Option<String>,
+    pub boot_rom_path: Option<String>,
+    // None picks the model from the cartridge header
+    pub model: Option<Model>,
+    pub renderer: Renderer,
+    // let the CPU access VRAM and OAM in every PPU mode
+    pub no_access_locks: bool,
+    // how long the headless runner runs
+    pub frames: u32,
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Run,
+        rom_path: DEFAULT_ROM_PATH.to_string(),
+        mapper: None,
+        patch_path: None,
+        boot_rom_path: None,
+        model: None,
+        renderer: Renderer::default(),
+        no_access_locks: false,
+        frames: DEFAULT_HEADLESS_FRAMES,
+    };
+    let mut first = true;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "info" if first => options.command = Command::Info,
+            "headless" if first => options.command = Command::Headless,
+            "--mapper" => {
+                let name = args.next().ok_or("--mapper needs a value")?;
+                options.mapper = Some(MapperKind::from_name(&name).ok_or_else(|| format!("Unknown mapper: {} (supported: {})", name, mapper_names()))?);
+            }
+            "--boot-rom" => options.boot_rom_path = Some(args.next().ok_or("--boot-rom needs a value")?),
+            "--model" => {
+                let name = args.next().ok_or("--model needs a value")?;
+                options.model = match name.as_str() {
+                    "auto" => None,
+                    _ => Some(Model::from_name(&name).ok_or_else(|| format!("Unknown model: {} (supported: auto, {})", name, model_names()))?),
+                };
+            }
+            "--renderer" => {
+                let name = args.next().ok_or("--renderer needs a value")?;
+                options.renderer = Renderer::from_name(&name).ok_or_else(|| format!("Unknown renderer: {} (supported: {})", name, renderer_names()))?;
+            }
+            "--no-access-locks" => options.no_access_locks = true,
+            "--frames" => {
+                let value = args.next().ok_or("--frames needs a value")?;
+                options.frames = value.parse().map_err(|_| format!("Invalid frame count: {}", value))?;
+            }
+            "--patch" => options.patch_path = Some(args.next().ok_or("--patch needs a value")?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => options.rom_path = arg,
+        }
+        first = false;
+    }
+    Ok(options)
+}
+
+fn mapper_names() -> String {
+    MapperKind::ALL.iter().map(|kind| kind.name()).collect::<Vec<_>>().join(", ")
+}
+
+fn model_names() -> String {
+    Model::ALL.iter().map(|model| model.name()).collect::<Vec<_>>().join(", ")
+}
+
+fn renderer_names() -> String {
+    Renderer::ALL.iter().map(|renderer| renderer.name()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn headless_takes_frame_count() {
+        let options = parse(&["headless", "--frames", "120", "game.gb"]).unwrap();
+        assert_eq!(options.command, Command::Headless);
+        assert_eq!(options.frames, 120);
+        assert_eq!(options.rom_path, "game.gb");
+    }
+
+    #[test]
+    fn headless_defaults_frame_count() {
+        let options = parse(&["headless", "game.gb"]).unwrap();
+        assert_eq!(options.command, Command::Headless);
+        assert_eq!(options.frames, DEFAULT_HEADLESS_FRAMES);
+    }
+
+    #[test]
+    fn headless_is_only_a_command_in_first_position() {
+        let options = parse(&["--frames", "1", "headless"]).unwrap();
+        assert_eq!(options.command, Command::Run);
+        assert_eq!(options.rom_path, "headless");
+    }
+
+    #[test]
+    fn invalid_frame_counts_are_rejected() {
+        for value in ["abc", "-1", "1.5", "", "4294967296"] {
+            assert_eq!(parse(&["headless", "--frames", value]).unwrap_err(), format!("Invalid frame count: {}", value));
+        }
+        assert_eq!(parse(&["headless", "--frames"]).unwrap_err(), "--frames needs a value");
+    }
+}
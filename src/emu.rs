@@ -1,8 +1,19 @@
+pub mod archive;
 pub mod boot;
+pub mod cartridge;
 pub mod cpu;
+pub mod frame_limiter;
+pub mod framebuffer;
+pub mod header;
 pub mod instruction_mapper;
 mod instructions;
 pub mod io;
+pub mod machine;
+pub mod mapper;
 pub mod memory;
+pub mod model;
+pub mod patch;
 pub mod registers;
 pub mod rom;
+pub mod save;
+pub mod state;
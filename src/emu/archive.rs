@@ -0,0 +1,136 @@
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use super::rom::RomError;
+
+const ZIP_MAGIC: [u8; 4] = [0x50, 0x4B, 0x03, 0x04];
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+// Splits `archive.zip:entry.gb` into the archive path and the requested entry.
+// A path that exists as is never gets split, so drive letters and odd file names keep working.
+pub fn split_entry(path: &str) -> (&str, Option<&str>) {
+    if Path::new(path).exists() {
+        return (path, None);
+    }
+    match path.rsplit_once(':') {
+        Some((archive, entry)) if !entry.is_empty() && Path::new(archive).is_file() => (archive, Some(entry)),
+        _ => (path, None),
+    }
+}
+
+// Returns the path with the container extension removed, `game.gb.gz` becomes `game.gb`
+pub fn strip_container_extension(path: &str) -> &str {
+    let (path, _) = split_entry(path);
+    path.strip_suffix(".gz").unwrap_or(path)
+}
+
+pub fn unpack(data: Vec<u8>, entry: Option<&str>) -> Result<Vec<u8>, RomError> {
+    if data.starts_with(&ZIP_MAGIC) {
+        return unpack_zip(data, entry);
+    }
+    if data.starts_with(&GZIP_MAGIC) {
+        let mut unpacked = Vec::new();
+        GzDecoder::new(&data[..]).read_to_end(&mut unpacked).map_err(|error| RomError::Archive(error.to_string()))?;
+        return Ok(unpacked);
+    }
+    Ok(data)
+}
+
+fn unpack_zip(data: Vec<u8>, entry: Option<&str>) -> Result<Vec<u8>, RomError> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|error| RomError::Archive(error.to_string()))?;
+    let name = match entry {
+        Some(entry) => entry.to_string(),
+        None => archive.file_names().find(|name| is_rom_name(name)).map(|name| name.to_string()).ok_or(RomError::NoRomInArchive)?,
+    };
+    let mut file = archive.by_name(&name).map_err(|_| RomError::MissingArchiveEntry(name.clone()))?;
+    let mut unpacked = Vec::new();
+    file.read_to_end(&mut unpacked).map_err(|error| RomError::Archive(error.to_string()))?;
+    Ok(unpacked)
+}
+
+fn is_rom_name(name: &str) -> bool {
+    let extension = Path::new(name).extension().and_then(|extension| extension.to_str()).unwrap_or("");
+    ROM_EXTENSIONS.iter().any(|rom_extension| extension.eq_ignore_ascii_case(rom_extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+    use crate::emu::rom::ROM;
+    use crate::emu::save;
+
+    fn make_zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn rom_bytes(tag: u8) -> Vec<u8> {
+        let mut data = vec![0; 0x8000];
+        data[0x0134] = tag;
+        data
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("redox-damage-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn plain_data_is_left_alone() {
+        assert_eq!(unpack(vec![1, 2, 3], None).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn unpacks_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&rom_bytes(b'G')).unwrap();
+        let data = encoder.finish().unwrap();
+        assert_eq!(unpack(data, None).unwrap(), rom_bytes(b'G'));
+    }
+
+    #[test]
+    fn picks_the_first_rom_in_a_zip() {
+        let data = make_zip(&[("readme.txt", b"hello"), ("game.GBC", &rom_bytes(b'A')), ("other.gb", &rom_bytes(b'B'))]);
+        assert_eq!(unpack(data.clone(), None).unwrap(), rom_bytes(b'A'));
+        assert_eq!(unpack(data.clone(), Some("other.gb")).unwrap(), rom_bytes(b'B'));
+        assert!(matches!(unpack(data, Some("missing.gb")), Err(RomError::MissingArchiveEntry(_))));
+        let data = make_zip(&[("readme.txt", b"hello")]);
+        assert!(matches!(unpack(data, None), Err(RomError::NoRomInArchive)));
+    }
+
+    #[test]
+    fn loads_a_named_entry_from_disk() {
+        let path = temp_path("collection.zip");
+        fs::write(&path, make_zip(&[("first.gb", &rom_bytes(b'A')), ("second.gb", &rom_bytes(b'B'))])).unwrap();
+        let entry_path = format!("{}:second.gb", path.display());
+        let rom = ROM::load_rom_from_file(&entry_path, None);
+        let save_path = save::get_save_path(&entry_path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(rom.unwrap().header.title, "B");
+        // the save sits next to the archive, not inside it
+        assert_eq!(save_path, temp_path("collection.sav"));
+    }
+
+    #[test]
+    fn save_path_follows_the_archive() {
+        assert_eq!(save::get_save_path("roms/game.gb.gz"), Path::new("roms/game.sav"));
+        assert_eq!(save::get_save_path("roms/game.zip"), Path::new("roms/game.sav"));
+        assert_eq!(save::get_save_path("roms/game.gbc"), Path::new("roms/game.sav"));
+    }
+}
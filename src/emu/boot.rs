@@ -1,9 +1,142 @@
+use std::fs;
+
+use super::cartridge::Cartridge;
+use super::cpu::CPU;
 use super::instructions::utils;
+use super::io::{ppu, timer};
 use super::memory::Memory;
+use super::model::Model;
+use super::rom::RomError;
+
+// Maps a boot ROM dump for `memory.model` over the start of the address space,
+// execution has to start at 0x0000 afterwards
+pub fn load_boot_rom(memory: &mut Memory, filepath: &str) -> Result<(), RomError> {
+    let data = fs::read(filepath).map_err(|error| RomError::Unreadable(filepath.to_string(), error))?;
+    let expected = memory.model.get_boot_rom_size();
+    if data.len() != expected {
+        return Err(RomError::BootRomSize { expected, actual: data.len() });
+    }
+    memory.boot_rom = Some(data);
+    Ok(())
+}
 
-pub fn boot_sequence(memory: &mut Memory) {
+// Dot on the last line of the frame the LCD is at when the boot ROM jumps to 0x0100.
+// The boot ROMs are timed differently, this is only close to the DMG one.
+const HANDOVER_DOT: u16 = 400;
+
+// HLE replacement for the boot ROM, used when no boot ROM file is given.
+// Leaves the machine in the state the boot ROM of `model` hands over to the game at 0x0100.
+pub fn boot_sequence(cpu: &mut CPU, memory: &mut Memory) {
+    let model = memory.model;
     unpack_and_load_logo(memory);
     setup_tile_data(memory);
+    if let Some(ref mut cartridge) = memory.cartridge {
+        cartridge.mapper.skip_boot_lock();
+    }
+    setup_registers(cpu, memory.cartridge.as_ref(), model);
+    setup_io_registers(memory, model);
+    cpu.pc = 0x0100;
+    cpu.sp = 0xFFFE;
+}
+
+fn setup_registers(cpu: &mut CPU, cartridge: Option<&Cartridge>, model: Model) {
+    let header = cartridge.map(|cartridge| &cartridge.header);
+    // the DMG and MGB boot ROMs leave H and C set unless the header checksum is 0x00
+    let header_checksum_flags: u8 = match header {
+        Some(header) if header.header_checksum == 0x00 => 0x80,
+        _ => 0xB0,
+    };
+    let (af, bc, de, hl): (u16, u16, u16, u16) = match model {
+        Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+        Model::Dmg => (0x0100 | header_checksum_flags as u16, 0x0013, 0x00D8, 0x014D),
+        Model::Mgb => (0xFF00 | header_checksum_flags as u16, 0x0013, 0x00D8, 0x014D),
+        Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+        Model::Sgb2 => (0xFF00, 0x0014, 0x0000, 0xC060),
+        Model::Cgb if header.is_some_and(|header| header.supports_cgb()) => (0x1180, 0x0000, 0xFF56, 0x000D),
+        Model::Cgb => get_cgb_dmg_mode_registers(cartridge),
+    };
+    cpu.registers.set_af(af);
+    cpu.registers.set_bc(bc);
+    cpu.registers.set_de(de);
+    cpu.registers.set_hl(hl);
+}
+
+// When running a DMG game the CGB boot ROM leaves traces of its palette lookup:
+// Nintendo published games get the checksum of the raw 16 title bytes (0x0134-0x0143) in B.
+// HL = 0x991A is the leftover tile map pointer listed in the published CGB register tables for
+// this case, it has not been checked on hardware here. No game reads HL before loading it,
+// so a wrong value could only show up in test ROMs that dump the registers.
+fn get_cgb_dmg_mode_registers(cartridge: Option<&Cartridge>) -> (u16, u16, u16, u16) {
+    let header = cartridge.map(|cartridge| &cartridge.header);
+    let nintendo_licensed = header.is_some_and(|header| header.old_licensee_code == 0x01 || (header.old_licensee_code == 0x33 && header.new_licensee_code == "01"));
+    if !nintendo_licensed {
+        return (0x1180, 0x0000, 0x0008, 0x007C);
+    }
+    let title_checksum = cartridge.map(|cartridge| cartridge.rom[0x0134..=0x0143].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))).unwrap_or(0);
+    (0x1180, (title_checksum as u16) << 8, 0x0008, 0x991A)
+}
+
+fn setup_io_registers(memory: &mut Memory, model: Model) {
+    let is_cgb = model.is_cgb();
+    let is_sgb = model.is_sgb();
+    let io_registers: [(usize, u8); 32] = [
+        (0xFF00, 0xCF),                             // P1
+        (0xFF01, 0x00),                             // SB
+        (0xFF02, if is_cgb { 0x7F } else { 0x7E }), // SC
+        (0xFF05, 0x00),                             // TIMA
+        (0xFF06, 0x00),                             // TMA
+        (0xFF07, 0xF8),                             // TAC
+        (0xFF0F, 0xE1),                             // IF
+        (0xFF10, 0x80),                             // NR10
+        (0xFF11, 0xBF),                             // NR11
+        (0xFF12, 0xF3),                             // NR12
+        (0xFF13, 0xFF),                             // NR13
+        (0xFF14, 0xBF),                             // NR14
+        (0xFF16, 0x3F),                             // NR21
+        (0xFF17, 0x00),                             // NR22
+        (0xFF18, 0xFF),                             // NR23
+        (0xFF19, 0xBF),                             // NR24
+        (0xFF1A, 0x7F),                             // NR30
+        (0xFF1B, 0xFF),                             // NR31
+        (0xFF1C, 0x9F),                             // NR32
+        (0xFF1D, 0xFF),                             // NR33
+        (0xFF1E, 0xBF),                             // NR34
+        (0xFF20, 0xFF),                             // NR41
+        (0xFF23, 0xBF),                             // NR44
+        (0xFF24, 0x77),                             // NR50
+        (0xFF25, 0xF3),                             // NR51
+        (0xFF26, if is_sgb { 0xF0 } else { 0xF1 }), // NR52
+        (0xFF40, 0x91),                             // LCDC
+        (0xFF41, 0x80),                             // STAT
+        (0xFF46, if is_cgb { 0x00 } else { 0xFF }), // DMA
+        (0xFF47, 0xFC),                             // BGP
+        (0xFF50, 0x01),                             // boot ROM disabled
+        (0xFFFF, 0x00),                             // IE
+    ];
+    // written directly, the values must not trigger any of the register side effects
+    for (address, value) in io_registers {
+        memory.addresses[address] = value;
+    }
+    // the PPU fills in LY and the low STAT bits, giving 0x00 and 0x85. The DMG0 is
+    // documented with STAT 0x81, but the coincidence flag is refreshed on the next dot anyway
+    ppu::start_on_last_line(memory, HANDOVER_DOT);
+    timer::set_divider(memory, (get_div(model) as u16) << 8);
+    if is_cgb {
+        memory.addresses[0xFF4D] = 0x7E; // KEY1
+        memory.addresses[0xFF4F] = 0xFE; // VBK
+        memory.addresses[0xFF70] = 0xF8; // SVBK
+    }
+}
+
+// DIV keeps counting during the boot ROM, so its value depends on how long each boot ROM runs
+fn get_div(model: Model) -> u8 {
+    match model {
+        Model::Dmg0 => 0x18,
+        Model::Dmg | Model::Mgb => 0xAB,
+        // SGB and CGB boot times vary with the cartridge, so these are only approximations
+        Model::Sgb | Model::Sgb2 => 0xD8,
+        Model::Cgb => 0x1E,
+    }
 }
 
 fn setup_tile_data(memory: &mut Memory) {
@@ -58,3 +191,73 @@ fn get_current_value_unpacked(byte: u8) -> u8 {
     }
     return value;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::rom::ROM;
+
+    fn boot(model: Model, licensee: u8) -> (CPU, Memory) {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom[0x014B] = licensee;
+        rom[0x014D] = crate::emu::header::get_header_checksum(&rom);
+        let mut memory = Memory { model, ..Default::default() };
+        memory.insert_cartridge(Cartridge::new(ROM::from_bytes(rom).unwrap(), None).unwrap());
+        let mut cpu = CPU::default();
+        boot_sequence(&mut cpu, &mut memory);
+        (cpu, memory)
+    }
+
+    fn registers(cpu: &CPU) -> [u16; 4] {
+        [cpu.registers.get_af(), cpu.registers.get_bc(), cpu.registers.get_de(), cpu.registers.get_hl()]
+    }
+
+    #[test]
+    fn cpu_registers_per_model() {
+        let expected = [
+            (Model::Dmg0, [0x0100, 0xFF13, 0x00C1, 0x8403]),
+            (Model::Dmg, [0x01B0, 0x0013, 0x00D8, 0x014D]),
+            (Model::Mgb, [0xFFB0, 0x0013, 0x00D8, 0x014D]),
+            (Model::Sgb, [0x0100, 0x0014, 0x0000, 0xC060]),
+            (Model::Sgb2, [0xFF00, 0x0014, 0x0000, 0xC060]),
+            (Model::Cgb, [0x1180, 0x0000, 0x0008, 0x007C]),
+        ];
+        for (model, registers_after_boot) in expected {
+            let (cpu, _) = boot(model, 0x00);
+            assert_eq!(registers(&cpu), registers_after_boot, "{}", model.name());
+            assert_eq!((cpu.pc, cpu.sp), (0x0100, 0xFFFE));
+        }
+    }
+
+    #[test]
+    fn cgb_leaves_the_title_checksum_for_nintendo_games() {
+        let (cpu, _) = boot(Model::Cgb, 0x01);
+        let checksum = b"TEST".iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(registers(&cpu), [0x1180, (checksum as u16) << 8, 0x0008, 0x991A]);
+    }
+
+    #[test]
+    fn io_registers_per_model() {
+        for model in Model::ALL {
+            let (_, memory) = boot(model, 0x00);
+            let read = |address| utils::read_byte_from_memory(&memory, address);
+            assert_eq!(read(0xFF04), get_div(model), "{}", model.name());
+            assert_eq!(read(0xFF40), 0x91);
+            // mode 1 with the coincidence flag set
+            assert_eq!(read(0xFF41), 0x85);
+            assert_eq!(read(0xFF44), 0x00);
+            assert_eq!(read(0xFF0F), 0xE1);
+            assert_eq!(read(0xFF26), if model.is_sgb() { 0xF0 } else { 0xF1 });
+        }
+    }
+
+    #[test]
+    fn first_frame_follows_the_handover() {
+        let (_, mut memory) = boot(Model::Dmg, 0x00);
+        // the rest of the last line, then the OAM scan of line 0 begins
+        memory.tick(((ppu::DOTS_PER_LINE - HANDOVER_DOT) / 4) as u8);
+        assert_eq!(utils::read_byte_from_memory(&memory, 0xFF41), 0x86);
+        assert_eq!(utils::read_byte_from_memory(&memory, 0xFF44), 0x00);
+    }
+}
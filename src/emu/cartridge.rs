@@ -0,0 +1,87 @@
+use super::header::CartridgeHeader;
+use super::mapper::{self, Mapper, MapperKind};
+use super::rom::{ROM, RomError};
+use super::save::BatterySave;
+use super::state::{StateError, StateReader, StateWriter};
+
+#[derive(Debug, Clone)]
+pub struct Cartridge {
+    pub header: CartridgeHeader,
+    pub rom: Vec<u8>,
+    pub ram: Vec<u8>,
+    pub mapper: Mapper,
+    pub battery_save: Option<BatterySave>,
+}
+
+impl Cartridge {
+    // `forced_mapper` overrides the detection heuristics, e.g. for carts with a misleading header
+    pub fn new(rom: ROM, forced_mapper: Option<MapperKind>) -> Result<Self, RomError> {
+        let ROM { data, header } = rom;
+        let mapper_kind = match forced_mapper {
+            Some(mapper_kind) => mapper_kind,
+            None => {
+                let mapper_kind = mapper::detect_mapper(&data, &header).ok_or(RomError::UnsupportedMapper(header.cartridge_type))?;
+                // bootleg headers are known to lie about the size, a short official dump is a broken one
+                if let Some(header_size) = header.get_rom_size()
+                    && mapper_kind.has_reliable_header()
+                    && data.len() < header_size
+                {
+                    return Err(RomError::SizeMismatch {
+                        header: header_size,
+                        actual: data.len(),
+                    });
+                }
+                mapper_kind
+            }
+        };
+        let ram = vec![0; header.get_ram_size()];
+        Ok(Self {
+            header,
+            rom: data,
+            ram,
+            mapper: Mapper::new(mapper_kind),
+            battery_save: None,
+        })
+    }
+
+    // The ROM is not part of the state, it has to be loaded separately
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.ram.len() as u32);
+        writer.write_bytes(&self.ram);
+        self.mapper.write_state(writer);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let len = reader.read_u32()? as usize;
+        if len != self.ram.len() {
+            return Err(StateError::CartridgeMismatch);
+        }
+        self.ram.copy_from_slice(reader.read_bytes(len)?);
+        self.mapper.read_state(reader)
+    }
+
+    pub fn read_rom(&self, address: usize) -> u8 {
+        self.mapper.read_rom(&self.rom, address)
+    }
+
+    pub fn write_rom(&mut self, address: usize, value: u8) {
+        self.mapper.write_rom(address, value);
+        if address < 0x2000
+            && value & 0x0F != 0x0A
+            && let Some(ref mut battery_save) = self.battery_save
+        {
+            battery_save.request_flush();
+        }
+    }
+
+    pub fn read_ram(&self, address: usize) -> u8 {
+        self.mapper.read_ram(&self.ram, address)
+    }
+
+    pub fn write_ram(&mut self, address: usize, value: u8) {
+        self.mapper.write_ram(&mut self.ram, address, value);
+        if let Some(ref mut battery_save) = self.battery_save {
+            battery_save.mark_dirty();
+        }
+    }
+}
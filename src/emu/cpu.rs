@@ -1,8 +1,38 @@
 use super::registers::Registers;
+use super::state::{StateError, StateReader, StateWriter};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct CPU {
     pub registers: Registers,
     pub sp: u16, // Stack Pointer
     pub pc: u16, // Program Counter
+    // interrupt master enable
+    pub ime: bool,
+    // EI only sets IME after the instruction that follows it
+    pub ime_pending: bool,
+    pub halted: bool,
+}
+
+impl CPU {
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        for value in [self.registers.get_af(), self.registers.get_bc(), self.registers.get_de(), self.registers.get_hl(), self.sp, self.pc] {
+            writer.write_u16(value);
+        }
+        writer.write_bool(self.ime);
+        writer.write_bool(self.ime_pending);
+        writer.write_bool(self.halted);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.registers.set_af(reader.read_u16()?);
+        self.registers.set_bc(reader.read_u16()?);
+        self.registers.set_de(reader.read_u16()?);
+        self.registers.set_hl(reader.read_u16()?);
+        self.sp = reader.read_u16()?;
+        self.pc = reader.read_u16()?;
+        self.ime = reader.read_bool()?;
+        self.ime_pending = reader.read_bool()?;
+        self.halted = reader.read_bool()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+pub const M_CYCLES_PER_SECOND: u64 = 1_048_576;
+// falling further behind than this drops the lost time instead of running fast to catch up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+// Keeps the emulated time in step with real time, a frontend sleeps for the returned delay after every frame
+#[derive(Debug)]
+pub struct FrameLimiter {
+    // real time at which the emulated time of the frames so far has passed
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(now: Instant) -> Self {
+        Self { next_frame: now }
+    }
+
+    // Time left until a frame of `cycles` M-cycles that was finished at `now` is due,
+    // a frame of 70224 dots takes 16.74 ms (59.73 frames per second)
+    pub fn get_delay(&mut self, cycles: u32, now: Instant) -> Duration {
+        self.next_frame += get_emulated_duration(cycles);
+        if self.next_frame > now {
+            return self.next_frame - now;
+        }
+        if now - self.next_frame > MAX_LAG {
+            self.next_frame = now;
+        }
+        Duration::ZERO
+    }
+}
+
+pub fn get_emulated_duration(cycles: u32) -> Duration {
+    Duration::from_nanos(cycles as u64 * 1_000_000_000 / M_CYCLES_PER_SECOND)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CYCLES_PER_FRAME: u32 = 17556;
+
+    #[test]
+    fn frames_last_as_long_as_on_hardware() {
+        assert_eq!(get_emulated_duration(CYCLES_PER_FRAME).as_micros(), 16742);
+        assert_eq!(get_emulated_duration(M_CYCLES_PER_SECOND as u32), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn fast_frames_wait_for_the_rest_of_the_frame() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(start);
+        let frame = get_emulated_duration(CYCLES_PER_FRAME);
+        assert_eq!(limiter.get_delay(CYCLES_PER_FRAME, start + Duration::from_millis(2)), frame - Duration::from_millis(2));
+        // the sleep is taken into account, the second frame is due one frame after the first
+        assert_eq!(limiter.get_delay(CYCLES_PER_FRAME, start + frame + Duration::from_millis(10)), frame - Duration::from_millis(10));
+    }
+
+    #[test]
+    fn slow_frames_are_made_up_later() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(start);
+        let frame = get_emulated_duration(CYCLES_PER_FRAME);
+        let late = Duration::from_millis(5);
+        assert_eq!(limiter.get_delay(CYCLES_PER_FRAME, start + frame + late), Duration::ZERO);
+        assert_eq!(limiter.get_delay(CYCLES_PER_FRAME, start + frame + late), frame - late);
+    }
+
+    #[test]
+    fn lag_beyond_the_limit_is_dropped() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(start);
+        let frame = get_emulated_duration(CYCLES_PER_FRAME);
+        let stall = start + Duration::from_secs(1);
+        assert_eq!(limiter.get_delay(CYCLES_PER_FRAME, stall), Duration::ZERO);
+        // the next frame gets its full time instead of running fast to catch up on the stall
+        assert_eq!(limiter.get_delay(CYCLES_PER_FRAME, stall), frame);
+    }
+}
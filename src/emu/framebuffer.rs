@@ -0,0 +1,102 @@
+use super::model::Model;
+use super::state::{StateError, StateReader, StateWriter};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+const BYTES_PER_PIXEL: usize = 4;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Pixel {
+    // shade after applying the palette register, 0 is the lightest
+    pub shade: u8,
+    // 2 bit color index from the tile data, before the palette was applied
+    pub color_index: u8,
+}
+
+// The 160x144 picture produced by the PPU, independent of how a frontend shows it
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pixels: Vec<Pixel>,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self {
+            pixels: vec![Pixel::default(); SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+}
+
+impl Framebuffer {
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Pixel) {
+        self.pixels[y * SCREEN_WIDTH + x] = pixel;
+    }
+
+    // row by row, starting at the top left
+    pub fn get_pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Pixel {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    // one byte per pixel, the shade in the low and the color index in the high nibble
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        for pixel in &self.pixels {
+            writer.write_u8(pixel.color_index << 4 | pixel.shade);
+        }
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let bytes = reader.read_bytes(self.pixels.len())?;
+        for (pixel, byte) in self.pixels.iter_mut().zip(bytes) {
+            *pixel = Pixel {
+                shade: byte & 0x03,
+                color_index: byte >> 4 & 0x03,
+            };
+        }
+        Ok(())
+    }
+
+    // blank screen, as shown while the LCD is off
+    pub fn clear(&mut self) {
+        self.pixels.fill(Pixel::default());
+    }
+
+    // RGBA with 4 bytes per pixel, in the same order as `get_pixels`.
+    // `show_color_index` shows the raw color indices instead of the shades.
+    pub fn to_rgba(&self, model: Model, show_color_index: bool) -> Vec<u8> {
+        let mut rgba = vec![0; self.pixels.len() * BYTES_PER_PIXEL];
+        self.write_rgba(&mut rgba, SCREEN_WIDTH * BYTES_PER_PIXEL, model, show_color_index);
+        rgba
+    }
+
+    // Same as `to_rgba`, but into an existing buffer whose rows start `pitch` bytes apart, like a locked texture
+    pub fn write_rgba(&self, buffer: &mut [u8], pitch: usize, model: Model, show_color_index: bool) {
+        for (y, row) in self.pixels.chunks(SCREEN_WIDTH).enumerate() {
+            for (x, pixel) in row.iter().enumerate() {
+                let value = if show_color_index { pixel.color_index } else { pixel.shade };
+                let [r, g, b] = get_shade_rgb(value, model);
+                let offset = y * pitch + x * BYTES_PER_PIXEL;
+                buffer[offset..offset + BYTES_PER_PIXEL].copy_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+    }
+}
+
+// only the original DMG has the green tinted LCD, later models show shades of grey
+pub fn get_shade_rgb(shade: u8, model: Model) -> [u8; 3] {
+    let green = matches!(model, Model::Dmg0 | Model::Dmg);
+    match shade {
+        0 if green => [155, 188, 15],
+        1 if green => [139, 172, 15],
+        2 if green => [48, 98, 48],
+        3 if green => [15, 56, 15],
+        0 => [224, 224, 224],
+        1 => [168, 168, 168],
+        2 => [88, 88, 88],
+        3 => [16, 16, 16],
+        _ => panic!("Invalid color value"),
+    }
+}
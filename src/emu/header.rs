@@ -0,0 +1,211 @@
+use std::fmt;
+
+use super::mapper;
+
+pub const HEADER_END: usize = 0x0150;
+
+#[derive(Debug, Clone)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub cgb_flag: u8,
+    pub sgb_flag: u8,
+    pub old_licensee_code: u8,
+    pub new_licensee_code: String,
+    pub cartridge_type: u8,
+    pub rom_size_code: u8,
+    pub ram_size_code: u8,
+    pub destination_code: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    pub validation: HeaderValidation,
+}
+
+#[derive(Debug, Clone)]
+pub struct HeaderValidation {
+    pub logo_valid: bool,
+    pub header_checksum_valid: bool,
+    pub global_checksum_valid: bool,
+    pub rom_size_valid: bool,
+}
+
+impl CartridgeHeader {
+    pub fn parse(romdata: &[u8]) -> Option<Self> {
+        if romdata.len() < HEADER_END {
+            return None;
+        }
+        let cgb_flag = romdata[0x0143];
+        // CGB era carts shortened the title to 11 characters to make room for the manufacturer code
+        let (title_end, manufacturer_code) = if cgb_flag & 0x80 > 0 {
+            (0x013F, Some(read_string(&romdata[0x013F..0x0143])))
+        } else {
+            (0x0144, None)
+        };
+        let mut header = Self {
+            title: read_string(&romdata[0x0134..title_end]),
+            manufacturer_code,
+            cgb_flag,
+            sgb_flag: romdata[0x0146],
+            old_licensee_code: romdata[0x014B],
+            new_licensee_code: read_string(&romdata[0x0144..0x0146]),
+            cartridge_type: romdata[0x0147],
+            rom_size_code: romdata[0x0148],
+            ram_size_code: romdata[0x0149],
+            destination_code: romdata[0x014A],
+            version: romdata[0x014C],
+            header_checksum: romdata[0x014D],
+            global_checksum: (romdata[0x014E] as u16) << 8 | romdata[0x014F] as u16,
+            validation: HeaderValidation {
+                logo_valid: mapper::has_logo(romdata),
+                header_checksum_valid: false,
+                global_checksum_valid: false,
+                rom_size_valid: false,
+            },
+        };
+        header.validation.header_checksum_valid = get_header_checksum(romdata) == header.header_checksum;
+        header.validation.global_checksum_valid = get_global_checksum(romdata) == header.global_checksum;
+        header.validation.rom_size_valid = header.get_rom_size() == Some(romdata.len());
+        Some(header)
+    }
+
+    pub fn get_rom_size(&self) -> Option<usize> {
+        match self.rom_size_code {
+            0x00..=0x08 => Some(0x8000 << self.rom_size_code),
+            _ => None,
+        }
+    }
+
+    pub fn get_ram_size(&self) -> usize {
+        match self.ram_size_code {
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            _ => 0,
+        }
+    }
+
+    pub fn get_licensee(&self) -> String {
+        // 0x33 means the code is stored in the two character new licensee field
+        if self.old_licensee_code == 0x33 {
+            self.new_licensee_code.clone()
+        } else {
+            format!("{:02X}", self.old_licensee_code)
+        }
+    }
+
+    pub fn get_cartridge_type_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "UNKNOWN",
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(self.cartridge_type, 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF)
+    }
+
+    pub fn has_rtc(&self) -> bool {
+        matches!(self.cartridge_type, 0x0F | 0x10)
+    }
+
+    pub fn supports_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 > 0
+    }
+
+    pub fn is_cgb_only(&self) -> bool {
+        self.cgb_flag == 0xC0
+    }
+
+    // the SGB functions are only enabled if the old licensee code says so as well
+    pub fn supports_sgb(&self) -> bool {
+        self.sgb_flag == 0x03 && self.old_licensee_code == 0x33
+    }
+
+    pub fn is_japanese(&self) -> bool {
+        self.destination_code == 0x00
+    }
+
+    pub fn get_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.validation.logo_valid {
+            warnings.push("Nintendo logo does not match, the dump may be corrupt or use a bootleg mapper".to_string());
+        }
+        if !self.validation.header_checksum_valid {
+            warnings.push(format!("Header checksum mismatch (header says {:02X})", self.header_checksum));
+        }
+        if !self.validation.global_checksum_valid {
+            warnings.push(format!("Global checksum mismatch (header says {:04X})", self.global_checksum));
+        }
+        if !self.validation.rom_size_valid {
+            warnings.push("ROM size does not match the header".to_string());
+        }
+        warnings
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        let valid = |value: bool| if value { "ok" } else { "INVALID" };
+        writeln!(f, "Title:             {}", self.title)?;
+        if let Some(ref manufacturer_code) = self.manufacturer_code {
+            writeln!(f, "Manufacturer:      {}", manufacturer_code)?;
+        }
+        writeln!(f, "CGB:               {:02X} (supported: {}, required: {})", self.cgb_flag, yes_no(self.supports_cgb()), yes_no(self.is_cgb_only()))?;
+        writeln!(f, "SGB:               {:02X} (supported: {})", self.sgb_flag, yes_no(self.supports_sgb()))?;
+        writeln!(f, "Licensee:          {}", self.get_licensee())?;
+        writeln!(f, "Cartridge type:    {:02X} ({})", self.cartridge_type, self.get_cartridge_type_name())?;
+        match self.get_rom_size() {
+            Some(size) => writeln!(f, "ROM size:          {:02X} ({} KiB)", self.rom_size_code, size / 1024)?,
+            None => writeln!(f, "ROM size:          {:02X} (unknown)", self.rom_size_code)?,
+        }
+        writeln!(f, "RAM size:          {:02X} ({} KiB)", self.ram_size_code, self.get_ram_size() / 1024)?;
+        writeln!(f, "Region:            {}", if self.is_japanese() { "Japan" } else { "Overseas" })?;
+        writeln!(f, "Version:           {}", self.version)?;
+        writeln!(f, "Logo:              {}", valid(self.validation.logo_valid))?;
+        writeln!(f, "Header checksum:   {:02X} ({})", self.header_checksum, valid(self.validation.header_checksum_valid))?;
+        write!(f, "Global checksum:   {:04X} ({})", self.global_checksum, valid(self.validation.global_checksum_valid))
+    }
+}
+
+fn read_string(bytes: &[u8]) -> String {
+    bytes.iter().take_while(|byte| **byte != 0).map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '?' }).collect::<String>().trim_end().to_string()
+}
+
+pub fn get_header_checksum(romdata: &[u8]) -> u8 {
+    romdata[0x0134..=0x014C].iter().fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1))
+}
+
+// sum of every byte in the ROM except the two checksum bytes themselves
+pub fn get_global_checksum(romdata: &[u8]) -> u16 {
+    romdata.iter().enumerate().filter(|(i, _)| *i != 0x014E && *i != 0x014F).fold(0u16, |checksum, (_, byte)| checksum.wrapping_add(*byte as u16))
+}
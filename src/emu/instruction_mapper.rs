@@ -3,78 +3,154 @@ use super::instructions;
 use super::instructions::enums::InstructionSourceTarget;
 use super::instructions::enums::JpOperands;
 use super::instructions::utils;
+use super::io::interrupt;
 use super::memory::Memory;
 
-pub fn execute_instruction(cpu: &mut CPU, memory: &mut Memory) {
-    let operation: u8 = utils::read_byte_from_memory(memory, cpu.pc as usize);
-    handle_instruction(operation, cpu, memory);
+// M-cycles per opcode, conditional jumps, calls and returns are listed with their not taken timing
+#[rustfmt::skip]
+const CYCLES: [u8; 256] = [
+    1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1,
+    1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4,
+    2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4,
+    3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4,
+    3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4,
+];
+
+// Executes the instruction at PC (or services an interrupt, or idles one M-cycle in HALT), clocks the rest of the machine alongside it and returns the M-cycles it took.
+// Loads, stores and read-modify-writes access memory in the last M-cycle of an instruction,
+// so the bus is clocked up to that cycle before the instruction runs and for the rest afterwards.
+pub fn execute_instruction(cpu: &mut CPU, memory: &mut Memory) -> u8 {
+    if cpu.halted {
+        if interrupt::get_pending(memory) == 0 {
+            memory.tick(1);
+            return 1;
+        }
+        cpu.halted = false;
+    }
+    if cpu.ime && interrupt::get_pending(memory) != 0 {
+        return interrupt::dispatch(cpu, memory);
+    }
+    let ei_delay_over = cpu.ime_pending;
+    let pc = cpu.pc;
+    let operation: u8 = utils::read_byte_from_memory(memory, pc as usize);
+    let cycles = if operation == 0xCB {
+        get_cb_cycles(utils::read_byte_from_memory(memory, pc as usize + 1))
+    } else {
+        CYCLES[operation as usize]
+    };
+    let cycles_before_access = cycles.saturating_sub(1);
+    memory.tick(cycles_before_access);
+    let taken = handle_instruction(operation, cpu, memory);
+    let cycles = cycles + get_branch_penalty(operation, taken);
+    memory.tick(cycles - cycles_before_access);
+    // a DI right after EI cancels the pending enable
+    if ei_delay_over && cpu.ime_pending {
+        cpu.ime = true;
+        cpu.ime_pending = false;
+    }
+    cycles
+}
+
+fn get_cb_cycles(cb_operation: u8) -> u8 {
+    match cb_operation {
+        // BIT n,(HL) only reads memory
+        0x40..=0x7F if cb_operation & 0x07 == 0x06 => 3,
+        _ if cb_operation & 0x07 == 0x06 => 4,
+        _ => 2,
+    }
+}
+
+// extra M-cycles of a conditional instruction when the condition was met
+fn get_branch_penalty(operation: u8, taken: bool) -> u8 {
+    if !taken {
+        return 0;
+    }
+    match operation {
+        0x20 | 0x28 | 0x30 | 0x38 => 1,
+        0xC2 | 0xCA | 0xD2 | 0xDA => 1,
+        0xC4 | 0xCC | 0xD4 | 0xDC => 3,
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => 3,
+        _ => 0,
+    }
 }
 
-fn handle_instruction(opcode: u8, cpu: &mut CPU, memory: &mut Memory) {
+// Returns whether a conditional jump, call or return was taken
+fn handle_instruction(opcode: u8, cpu: &mut CPU, memory: &mut Memory) -> bool {
     // commented operations are not supported
     match opcode {
         0x00 => instructions::misc::nop(cpu),
         0x01 => instructions::load::ld_n16(cpu, memory, InstructionSourceTarget::BC),
         0x02 => instructions::load::ld_a_to_pointer(cpu, memory, InstructionSourceTarget::BcAsPointer),
-        0x03 => instructions::increment::inc(cpu, InstructionSourceTarget::BC),
-        0x04 => instructions::increment::inc(cpu, InstructionSourceTarget::B),
-        0x05 => instructions::decrement::dec(cpu, InstructionSourceTarget::B),
+        0x03 => instructions::increment::inc(cpu, memory, InstructionSourceTarget::BC),
+        0x04 => instructions::increment::inc(cpu, memory, InstructionSourceTarget::B),
+        0x05 => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::B),
         0x06 => instructions::load::ld_n8(cpu, memory, InstructionSourceTarget::B),
         0x07 => instructions::bit::rlca(cpu),
         0x08 => instructions::load::ld_sp_to_n16(cpu, memory),
         0x09 => instructions::arithmetic::add_to_hl(cpu, InstructionSourceTarget::BC),
         0x0A => instructions::load::ld_pointer_to_a(cpu, memory, InstructionSourceTarget::BcAsPointer),
-        0x0B => instructions::decrement::dec(cpu, InstructionSourceTarget::BC),
-        0x0C => instructions::increment::inc(cpu, InstructionSourceTarget::C),
-        0x0D => instructions::decrement::dec(cpu, InstructionSourceTarget::C),
+        0x0B => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::BC),
+        0x0C => instructions::increment::inc(cpu, memory, InstructionSourceTarget::C),
+        0x0D => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::C),
         0x0E => instructions::load::ld_n8(cpu, memory, InstructionSourceTarget::C),
         0x0F => instructions::bit::rrca(cpu),
         0x10 => instructions::misc::stop(cpu, memory),
         0x11 => instructions::load::ld_n16(cpu, memory, InstructionSourceTarget::DE),
         0x12 => instructions::load::ld_a_to_pointer(cpu, memory, InstructionSourceTarget::DeAsPointer),
-        0x13 => instructions::increment::inc(cpu, InstructionSourceTarget::DE),
-        0x14 => instructions::increment::inc(cpu, InstructionSourceTarget::D),
-        0x15 => instructions::decrement::dec(cpu, InstructionSourceTarget::D),
+        0x13 => instructions::increment::inc(cpu, memory, InstructionSourceTarget::DE),
+        0x14 => instructions::increment::inc(cpu, memory, InstructionSourceTarget::D),
+        0x15 => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::D),
         0x16 => instructions::load::ld_n8(cpu, memory, InstructionSourceTarget::D),
         0x17 => instructions::bit::rla(cpu),
         0x18 => instructions::jump::jr(cpu, memory),
         0x19 => instructions::arithmetic::add_to_hl(cpu, InstructionSourceTarget::DE),
         0x1A => instructions::load::ld_pointer_to_a(cpu, memory, InstructionSourceTarget::DeAsPointer),
-        0x1B => instructions::decrement::dec(cpu, InstructionSourceTarget::DE),
-        0x1C => instructions::increment::inc(cpu, InstructionSourceTarget::E),
-        0x1D => instructions::decrement::dec(cpu, InstructionSourceTarget::E),
+        0x1B => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::DE),
+        0x1C => instructions::increment::inc(cpu, memory, InstructionSourceTarget::E),
+        0x1D => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::E),
         0x1E => instructions::load::ld_n8(cpu, memory, InstructionSourceTarget::E),
         0x1F => instructions::bit::rra(cpu),
-        0x20 => instructions::jump::jr_with_operand(cpu, memory, JpOperands::NZ),
+        0x20 => return instructions::jump::jr_with_operand(cpu, memory, JpOperands::NZ),
         0x21 => instructions::load::ld_n16(cpu, memory, InstructionSourceTarget::HL),
         0x22 => instructions::load::ld_a_to_pointer(cpu, memory, InstructionSourceTarget::HlPlus),
-        0x23 => instructions::increment::inc(cpu, InstructionSourceTarget::HL),
-        0x24 => instructions::increment::inc(cpu, InstructionSourceTarget::H),
-        0x25 => instructions::decrement::dec(cpu, InstructionSourceTarget::H),
+        0x23 => instructions::increment::inc(cpu, memory, InstructionSourceTarget::HL),
+        0x24 => instructions::increment::inc(cpu, memory, InstructionSourceTarget::H),
+        0x25 => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::H),
         0x26 => instructions::load::ld_n8(cpu, memory, InstructionSourceTarget::H),
         0x27 => instructions::misc::daa(cpu),
-        0x28 => instructions::jump::jr_with_operand(cpu, memory, JpOperands::Z),
+        0x28 => return instructions::jump::jr_with_operand(cpu, memory, JpOperands::Z),
         0x29 => instructions::arithmetic::add_to_hl(cpu, InstructionSourceTarget::HL),
         0x2A => instructions::load::ld_pointer_to_a(cpu, memory, InstructionSourceTarget::HlPlus),
-        0x2B => instructions::decrement::dec(cpu, InstructionSourceTarget::HL),
-        0x2C => instructions::increment::inc(cpu, InstructionSourceTarget::L),
-        0x2D => instructions::decrement::dec(cpu, InstructionSourceTarget::L),
+        0x2B => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::HL),
+        0x2C => instructions::increment::inc(cpu, memory, InstructionSourceTarget::L),
+        0x2D => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::L),
         0x2E => instructions::load::ld_n8(cpu, memory, InstructionSourceTarget::L),
         0x2F => instructions::misc::cpl(cpu),
-        0x30 => instructions::jump::jr_with_operand(cpu, memory, JpOperands::NC),
+        0x30 => return instructions::jump::jr_with_operand(cpu, memory, JpOperands::NC),
         0x31 => instructions::load::ld_n16(cpu, memory, InstructionSourceTarget::SP),
         0x32 => instructions::load::ld_a_to_pointer(cpu, memory, InstructionSourceTarget::HlMinus),
-        0x33 => instructions::increment::inc(cpu, InstructionSourceTarget::SP),
+        0x33 => instructions::increment::inc(cpu, memory, InstructionSourceTarget::SP),
         0x34 => instructions::increment::inc_r8_at_hl(cpu, memory),
         0x35 => instructions::decrement::dec_r8_at_hl(cpu, memory),
         0x36 => instructions::load::ld_n8(cpu, memory, InstructionSourceTarget::HlAsPointer),
         0x37 => instructions::misc::scf(cpu),
-        0x38 => instructions::jump::jr_with_operand(cpu, memory, JpOperands::C),
+        0x38 => return instructions::jump::jr_with_operand(cpu, memory, JpOperands::C),
         0x39 => instructions::arithmetic::add_to_hl(cpu, InstructionSourceTarget::SP),
         0x3A => instructions::load::ld_pointer_to_a(cpu, memory, InstructionSourceTarget::HlMinus),
-        0x3B => instructions::decrement::dec(cpu, InstructionSourceTarget::SP),
-        0x3C => instructions::increment::inc(cpu, InstructionSourceTarget::A),
-        0x3D => instructions::decrement::dec(cpu, InstructionSourceTarget::A),
+        0x3B => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::SP),
+        0x3C => instructions::increment::inc(cpu, memory, InstructionSourceTarget::A),
+        0x3D => instructions::decrement::dec(cpu, memory, InstructionSourceTarget::A),
         0x3E => instructions::load::ld_n8(cpu, memory, InstructionSourceTarget::A),
         0x3F => instructions::misc::ccf(cpu),
         0x40 => instructions::load::ld_r8_r8(cpu, memory, InstructionSourceTarget::B, InstructionSourceTarget::B),
@@ -205,38 +281,38 @@ fn handle_instruction(opcode: u8, cpu: &mut CPU, memory: &mut Memory) {
         0xBD => instructions::arithmetic::cp(cpu, InstructionSourceTarget::L),
         0xBE => instructions::arithmetic::cp_hl(cpu, memory),
         0xBF => instructions::arithmetic::cp(cpu, InstructionSourceTarget::A),
-        0xC0 => instructions::jump::ret_with_operand(cpu, memory, JpOperands::NZ),
+        0xC0 => return instructions::jump::ret_with_operand(cpu, memory, JpOperands::NZ),
         0xC1 => instructions::load::pop(cpu, memory, InstructionSourceTarget::BC),
-        0xC2 => instructions::jump::jp_with_operand(cpu, memory, JpOperands::NZ),
+        0xC2 => return instructions::jump::jp_with_operand(cpu, memory, JpOperands::NZ),
         0xC3 => instructions::jump::jp(cpu, memory),
-        0xC4 => instructions::jump::call_with_operand(cpu, memory, JpOperands::NZ),
+        0xC4 => return instructions::jump::call_with_operand(cpu, memory, JpOperands::NZ),
         0xC5 => instructions::load::push(cpu, memory, InstructionSourceTarget::BC),
         0xC6 => instructions::arithmetic::add_n8(cpu, memory),
-        0xC7 => instructions::jump::rst(cpu, 0x00),
-        0xC8 => instructions::jump::ret_with_operand(cpu, memory, JpOperands::Z),
+        0xC7 => instructions::jump::rst(cpu, memory, 0x00),
+        0xC8 => return instructions::jump::ret_with_operand(cpu, memory, JpOperands::Z),
         0xC9 => instructions::jump::ret(cpu, memory),
-        0xCA => instructions::jump::jp_with_operand(cpu, memory, JpOperands::Z),
+        0xCA => return instructions::jump::jp_with_operand(cpu, memory, JpOperands::Z),
         0xCB => handle_cb_prefixed_instruction(cpu, memory),
-        0xCC => instructions::jump::call_with_operand(cpu, memory, JpOperands::Z),
+        0xCC => return instructions::jump::call_with_operand(cpu, memory, JpOperands::Z),
         0xCD => instructions::jump::call(cpu, memory),
         0xCE => instructions::arithmetic::adc_n8(cpu, memory),
-        0xCF => instructions::jump::rst(cpu, 0x08),
-        0xD0 => instructions::jump::ret_with_operand(cpu, memory, JpOperands::NC),
+        0xCF => instructions::jump::rst(cpu, memory, 0x08),
+        0xD0 => return instructions::jump::ret_with_operand(cpu, memory, JpOperands::NC),
         0xD1 => instructions::load::pop(cpu, memory, InstructionSourceTarget::DE),
-        0xD2 => instructions::jump::jp_with_operand(cpu, memory, JpOperands::NC),
+        0xD2 => return instructions::jump::jp_with_operand(cpu, memory, JpOperands::NC),
         // 0xD3 => (),
-        0xD4 => instructions::jump::call_with_operand(cpu, memory, JpOperands::NC),
+        0xD4 => return instructions::jump::call_with_operand(cpu, memory, JpOperands::NC),
         0xD5 => instructions::load::push(cpu, memory, InstructionSourceTarget::DE),
         0xD6 => instructions::arithmetic::sub_n8(cpu, memory),
-        0xD7 => instructions::jump::rst(cpu, 0x10),
-        0xD8 => instructions::jump::ret_with_operand(cpu, memory, JpOperands::C),
+        0xD7 => instructions::jump::rst(cpu, memory, 0x10),
+        0xD8 => return instructions::jump::ret_with_operand(cpu, memory, JpOperands::C),
         0xD9 => instructions::jump::reti(cpu, memory),
-        0xDA => instructions::jump::jp_with_operand(cpu, memory, JpOperands::C),
+        0xDA => return instructions::jump::jp_with_operand(cpu, memory, JpOperands::C),
         // 0xDB => (),
-        0xDC => instructions::jump::call_with_operand(cpu, memory, JpOperands::C),
+        0xDC => return instructions::jump::call_with_operand(cpu, memory, JpOperands::C),
         // 0xDD => (),
         0xDE => instructions::arithmetic::sbc_n8(cpu, memory),
-        0xDF => instructions::jump::rst(cpu, 0x18),
+        0xDF => instructions::jump::rst(cpu, memory, 0x18),
         0xE0 => instructions::load::ldh_a_to_a8(cpu, memory),
         0xE1 => instructions::load::pop(cpu, memory, InstructionSourceTarget::HL),
         0xE2 => instructions::load::ld_a_to_pointer(cpu, memory, InstructionSourceTarget::CAsPointer),
@@ -244,7 +320,7 @@ fn handle_instruction(opcode: u8, cpu: &mut CPU, memory: &mut Memory) {
         // 0xE4 => (),
         0xE5 => instructions::load::push(cpu, memory, InstructionSourceTarget::HL),
         0xE6 => instructions::logical::and_n8(cpu, memory),
-        0xE7 => instructions::jump::rst(cpu, 0x20),
+        0xE7 => instructions::jump::rst(cpu, memory, 0x20),
         0xE8 => instructions::arithmetic::add_to_sp(cpu, memory),
         0xE9 => instructions::jump::jp_hl(cpu),
         0xEA => instructions::load::ld_a_to_a16(cpu, memory),
@@ -252,7 +328,7 @@ fn handle_instruction(opcode: u8, cpu: &mut CPU, memory: &mut Memory) {
         // 0xEC => (),
         // 0xED => (),
         0xEE => instructions::logical::xor_n8(cpu, memory),
-        0xEF => instructions::jump::rst(cpu, 0x28),
+        0xEF => instructions::jump::rst(cpu, memory, 0x28),
         0xF0 => instructions::load::ldh_a8_to_a(cpu, memory),
         0xF1 => instructions::load::pop(cpu, memory, InstructionSourceTarget::AF),
         0xF2 => instructions::load::ld_pointer_to_a(cpu, memory, InstructionSourceTarget::CAsPointer),
@@ -260,7 +336,7 @@ fn handle_instruction(opcode: u8, cpu: &mut CPU, memory: &mut Memory) {
         // 0xF4 => (),
         0xF5 => instructions::load::push(cpu, memory, InstructionSourceTarget::AF),
         0xF6 => instructions::logical::or_n8(cpu, memory),
-        0xF7 => instructions::jump::rst(cpu, 0x30),
+        0xF7 => instructions::jump::rst(cpu, memory, 0x30),
         0xF8 => instructions::load::ld_sp_and_e8_to_hl(cpu, memory),
         0xF9 => instructions::load::ld_hl_to_sp(cpu),
         0xFA => instructions::load::ld_a16_to_a(cpu, memory),
@@ -268,9 +344,10 @@ fn handle_instruction(opcode: u8, cpu: &mut CPU, memory: &mut Memory) {
         // 0xFC => (),
         // 0xFD => (),
         0xFE => instructions::arithmetic::cp_n8(cpu, memory),
-        0xFF => instructions::jump::rst(cpu, 0x38),
+        0xFF => instructions::jump::rst(cpu, memory, 0x38),
         _ => panic!("Operation not supported"),
     }
+    false
 }
 
 fn handle_cb_prefixed_instruction(cpu: &mut CPU, memory: &mut Memory) {
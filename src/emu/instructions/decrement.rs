@@ -1,9 +1,13 @@
 use super::enums::InstructionSourceTarget;
 use crate::emu::cpu::CPU;
-use crate::emu::instructions::utils;
+use crate::emu::instructions::{increment, utils};
+use crate::emu::io::ppu::oam_bug;
 use crate::emu::memory::Memory;
 
-pub fn dec(cpu: &mut CPU, target: InstructionSourceTarget) {
+pub fn dec(cpu: &mut CPU, memory: &mut Memory, target: InstructionSourceTarget) {
+    if let Some(value) = increment::get_r16(cpu, &target) {
+        oam_bug::corrupt_write(memory, value, 0);
+    }
     match target {
         InstructionSourceTarget::BC => cpu.registers.set_bc(get_new_value_after_dec_u16(cpu.registers.get_bc())),
         InstructionSourceTarget::DE => cpu.registers.set_de(get_new_value_after_dec_u16(cpu.registers.get_de())),
@@ -1,10 +1,14 @@
 use crate::emu::cpu::CPU;
 use crate::emu::instructions::utils;
+use crate::emu::io::ppu::oam_bug;
 use crate::emu::memory::Memory;
 
 use super::enums::InstructionSourceTarget;
 
-pub fn inc(cpu: &mut CPU, target: InstructionSourceTarget) {
+pub fn inc(cpu: &mut CPU, memory: &mut Memory, target: InstructionSourceTarget) {
+    if let Some(value) = get_r16(cpu, &target) {
+        oam_bug::corrupt_write(memory, value, 0);
+    }
     match target {
         InstructionSourceTarget::BC => cpu.registers.set_bc(get_new_value_after_inc_u16(cpu.registers.get_bc())),
         InstructionSourceTarget::DE => cpu.registers.set_de(get_new_value_after_inc_u16(cpu.registers.get_de())),
@@ -61,6 +65,16 @@ fn inc_r8(cpu: &mut CPU, target: InstructionSourceTarget) {
     }
 }
 
+pub fn get_r16(cpu: &CPU, target: &InstructionSourceTarget) -> Option<u16> {
+    match target {
+        InstructionSourceTarget::BC => Some(cpu.registers.get_bc()),
+        InstructionSourceTarget::DE => Some(cpu.registers.get_de()),
+        InstructionSourceTarget::HL => Some(cpu.registers.get_hl()),
+        InstructionSourceTarget::SP => Some(cpu.sp),
+        _ => None,
+    }
+}
+
 fn get_new_value_after_inc(original_value: u8) -> u8 {
     if original_value == u8::MAX {
         return 0;
@@ -2,10 +2,10 @@ use crate::emu::cpu::CPU;
 use crate::emu::memory::Memory;
 
 use super::enums::JpOperands;
-use super::misc;
 use super::utils;
 
-pub fn jp_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: JpOperands) {
+// Returns whether the condition was met and the jump was taken
+pub fn jp_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: JpOperands) -> bool {
     let flag_value = match additional_operand {
         JpOperands::Z => cpu.registers.get_flag_z(),
         JpOperands::NZ => !cpu.registers.get_flag_z(),
@@ -17,6 +17,7 @@ pub fn jp_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: J
     } else {
         cpu.pc += 3;
     }
+    flag_value
 }
 
 pub fn jp(cpu: &mut CPU, memory: &Memory) {
@@ -27,7 +28,7 @@ pub fn jp_hl(cpu: &mut CPU) {
     cpu.pc = cpu.registers.get_hl();
 }
 
-pub fn jr_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: JpOperands) {
+pub fn jr_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: JpOperands) -> bool {
     let flag_value = match additional_operand {
         JpOperands::Z => cpu.registers.get_flag_z(),
         JpOperands::NZ => !cpu.registers.get_flag_z(),
@@ -39,6 +40,7 @@ pub fn jr_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: J
     } else {
         cpu.pc += 2;
     }
+    flag_value
 }
 
 pub fn jr(cpu: &mut CPU, memory: &Memory) {
@@ -51,7 +53,7 @@ pub fn jr(cpu: &mut CPU, memory: &Memory) {
     }
 }
 
-pub fn call_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: JpOperands) {
+pub fn call_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: JpOperands) -> bool {
     let flag_value = match additional_operand {
         JpOperands::Z => cpu.registers.get_flag_z(),
         JpOperands::NZ => !cpu.registers.get_flag_z(),
@@ -63,19 +65,16 @@ pub fn call_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand:
     } else {
         cpu.pc += 3;
     }
+    flag_value
 }
 
 pub fn call(cpu: &mut CPU, memory: &mut Memory) {
-    cpu.pc += 3;
-    let value1: u8 = (cpu.pc >> 8) as u8;
-    let value2: u8 = (cpu.pc & 0x00FF) as u8;
-    memory.addresses[cpu.sp as usize] = value1;
-    memory.addresses[cpu.sp as usize - 1] = value2;
-    cpu.sp -= 2;
-    jp(cpu, memory);
+    let address = utils::get_next_bytes_little_endian(cpu, memory);
+    utils::push_word(cpu, memory, cpu.pc.wrapping_add(3), 0);
+    cpu.pc = address;
 }
 
-pub fn ret_with_operand(cpu: &mut CPU, memory: &Memory, additional_operand: JpOperands) {
+pub fn ret_with_operand(cpu: &mut CPU, memory: &mut Memory, additional_operand: JpOperands) -> bool {
     let flag_value = match additional_operand {
         JpOperands::Z => cpu.registers.get_flag_z(),
         JpOperands::NZ => !cpu.registers.get_flag_z(),
@@ -87,22 +86,89 @@ pub fn ret_with_operand(cpu: &mut CPU, memory: &Memory, additional_operand: JpOp
     } else {
         cpu.pc += 1;
     }
+    flag_value
 }
 
-pub fn ret(cpu: &mut CPU, memory: &Memory) {
-    let value1: u16 = memory.addresses[cpu.sp as usize] as u16;
-    let value2: u16 = memory.addresses[cpu.sp as usize + 1] as u16;
-    let value: u16 = value2 << 8 | value1;
-    cpu.pc = value;
-    cpu.sp += 2;
+// the new PC is set in an extra M-cycle after both reads
+pub fn ret(cpu: &mut CPU, memory: &mut Memory) {
+    cpu.pc = utils::pop_word(cpu, memory, 1);
 }
 
-pub fn reti(cpu: &mut CPU, memory: &Memory) {
-    misc::ei(cpu);
+// unlike EI there is no delay, IME is set right away
+pub fn reti(cpu: &mut CPU, memory: &mut Memory) {
+    cpu.ime = true;
     ret(cpu, memory);
 }
 
-pub fn rst(cpu: &mut CPU, address: u8) {
-    cpu.sp = cpu.pc + 1;
+pub fn rst(cpu: &mut CPU, memory: &mut Memory, address: u8) {
+    utils::push_word(cpu, memory, cpu.pc.wrapping_add(1), 0);
     cpu.pc = address as u16;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::emu::cartridge::Cartridge;
+    use crate::emu::cpu::CPU;
+    use crate::emu::instruction_mapper;
+    use crate::emu::instructions::utils;
+    use crate::emu::memory::Memory;
+    use crate::emu::rom::ROM;
+
+    // runs a single instruction from work RAM and returns its M-cycles and the new PC
+    fn run(code: &[u8], zero: bool) -> (u8, u16) {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.addresses[0xC000..0xC000 + code.len()].copy_from_slice(code);
+        cpu.pc = 0xC000;
+        cpu.registers.set_flag_z(zero);
+        let cycles = instruction_mapper::execute_instruction(&mut cpu, &mut memory);
+        (cycles, cpu.pc)
+    }
+
+    #[test]
+    fn taken_branch_to_next_instruction_costs_extra_cycles() {
+        // JR NZ,+0
+        assert_eq!(run(&[0x20, 0x00], false), (3, 0xC002));
+        assert_eq!(run(&[0x20, 0x00], true), (2, 0xC002));
+        // JP NZ,0xC003
+        assert_eq!(run(&[0xC2, 0x03, 0xC0], false), (4, 0xC003));
+        assert_eq!(run(&[0xC2, 0x03, 0xC0], true), (3, 0xC003));
+    }
+
+    #[test]
+    fn boot_rom_returns_through_the_stack_and_unmaps_itself() {
+        let mut boot_rom = vec![0; 0x100];
+        let code: [(usize, &[u8]); 4] = [
+            // LD SP,0xFFFE; CALL 0x0010
+            (0x00, &[0x31, 0xFE, 0xFF, 0xCD, 0x10, 0x00]),
+            // LD A,0x01; LDH [0x50],A
+            (0x06, &[0x3E, 0x01, 0xE0, 0x50]),
+            // RST 0x38; RET
+            (0x10, &[0xFF, 0xC9]),
+            // LD B,0x42; RET
+            (0x38, &[0x06, 0x42, 0xC9]),
+        ];
+        for (address, bytes) in code {
+            boot_rom[address..address + bytes.len()].copy_from_slice(bytes);
+        }
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0xAA;
+        let mut memory = Memory::default();
+        memory.insert_cartridge(Cartridge::new(ROM::from_bytes(rom).unwrap(), None).unwrap());
+        memory.boot_rom = Some(boot_rom);
+        let mut cpu = CPU::default();
+
+        let mut pcs = Vec::new();
+        while cpu.pc != 0x000A && pcs.len() < 20 {
+            pcs.push(cpu.pc);
+            instruction_mapper::execute_instruction(&mut cpu, &mut memory);
+        }
+        assert_eq!(pcs, [0x00, 0x03, 0x10, 0x38, 0x3A, 0x11, 0x06, 0x08]);
+        assert_eq!(cpu.registers.b, 0x42);
+        assert_eq!(cpu.sp, 0xFFFE);
+        // CALL left its return address below the initial SP, high byte on top
+        assert_eq!(memory.addresses[0xFFFC..0xFFFE], [0x06, 0x00]);
+        assert!(memory.boot_rom.is_none());
+        assert_eq!(utils::peek_byte(&memory, 0x0000), 0xAA);
+    }
+}
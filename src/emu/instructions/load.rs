@@ -1,6 +1,7 @@
 use super::enums::InstructionSourceTarget;
 use super::utils;
 use crate::emu::cpu::CPU;
+use crate::emu::io::ppu::oam_bug;
 use crate::emu::memory::Memory;
 
 pub fn ld_r8_r8(cpu: &mut CPU, memory: &mut Memory, target: InstructionSourceTarget, source: InstructionSourceTarget) {
@@ -82,11 +83,14 @@ pub fn ld_a_to_pointer(cpu: &mut CPU, memory: &mut Memory, target_pointer: Instr
         }
         _ => panic!("Target pointer not supported"),
     };
+    if matches!(target_pointer, InstructionSourceTarget::HlPlus | InstructionSourceTarget::HlMinus) {
+        oam_bug::corrupt_write(memory, address, 0);
+    }
     utils::write_byte_to_memory(memory, address as usize, cpu.registers.a);
     cpu.pc += 1;
 }
 
-pub fn ld_pointer_to_a(cpu: &mut CPU, memory: &Memory, source_pointer: InstructionSourceTarget) {
+pub fn ld_pointer_to_a(cpu: &mut CPU, memory: &mut Memory, source_pointer: InstructionSourceTarget) {
     let address = match source_pointer {
         InstructionSourceTarget::BcAsPointer => cpu.registers.get_bc(),
         InstructionSourceTarget::DeAsPointer => cpu.registers.get_de(),
@@ -103,6 +107,9 @@ pub fn ld_pointer_to_a(cpu: &mut CPU, memory: &Memory, source_pointer: Instructi
         }
         _ => panic!("Source pointer not supported"),
     };
+    if matches!(source_pointer, InstructionSourceTarget::HlPlus | InstructionSourceTarget::HlMinus) {
+        oam_bug::corrupt_read_increase(memory, address, 0);
+    }
     cpu.registers.a = utils::read_byte_from_memory(memory, address as usize);
     cpu.pc += 1;
 }
@@ -125,10 +132,8 @@ pub fn ld_sp_and_e8_to_hl(cpu: &mut CPU, memory: &Memory) {
     cpu.pc += 2;
 }
 
-pub fn pop(cpu: &mut CPU, memory: &Memory, target: InstructionSourceTarget) {
-    let value1 = utils::read_byte_from_memory(memory, cpu.pc as usize) as u16;
-    let value2 = utils::read_byte_from_memory(memory, cpu.pc as usize + 1) as u16;
-    let value: u16 = value2 << 8 | value1;
+pub fn pop(cpu: &mut CPU, memory: &mut Memory, target: InstructionSourceTarget) {
+    let value = utils::pop_word(cpu, memory, 0);
     match target {
         InstructionSourceTarget::BC => cpu.registers.set_bc(value),
         InstructionSourceTarget::DE => cpu.registers.set_de(value),
@@ -136,21 +141,18 @@ pub fn pop(cpu: &mut CPU, memory: &Memory, target: InstructionSourceTarget) {
         InstructionSourceTarget::AF => cpu.registers.set_af(value),
         _ => panic!("Target not supported"),
     }
-    cpu.sp += 2;
     cpu.pc += 1;
 }
 
 pub fn push(cpu: &mut CPU, memory: &mut Memory, source: InstructionSourceTarget) {
-    let values: (u8, u8) = match source {
-        InstructionSourceTarget::BC => (cpu.registers.b, cpu.registers.c),
-        InstructionSourceTarget::DE => (cpu.registers.d, cpu.registers.e),
-        InstructionSourceTarget::HL => (cpu.registers.h, cpu.registers.l),
-        InstructionSourceTarget::AF => (cpu.registers.a, cpu.registers.get_f()),
+    let value = match source {
+        InstructionSourceTarget::BC => cpu.registers.get_bc(),
+        InstructionSourceTarget::DE => cpu.registers.get_de(),
+        InstructionSourceTarget::HL => cpu.registers.get_hl(),
+        InstructionSourceTarget::AF => cpu.registers.get_af(),
         _ => panic!("Target not supported"),
     };
-    utils::write_byte_to_memory(memory, cpu.sp as usize, values.0);
-    utils::write_byte_to_memory(memory, cpu.sp as usize - 1, values.1);
-    cpu.sp -= 2;
+    utils::push_word(cpu, memory, value, 0);
     cpu.pc += 1;
 }
 
@@ -182,3 +184,73 @@ pub fn ld_hl_to_sp(cpu: &mut CPU) {
     cpu.sp = cpu.registers.get_hl();
     cpu.pc += 1;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::emu::cpu::CPU;
+    use crate::emu::instruction_mapper;
+    use crate::emu::memory::Memory;
+
+    #[test]
+    fn pop_reads_what_push_wrote() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        // PUSH BC, POP DE
+        memory.addresses[0xC000..0xC002].copy_from_slice(&[0xC5, 0xD1]);
+        cpu.pc = 0xC000;
+        cpu.sp = 0xFFFE;
+        cpu.registers.set_bc(0x1234);
+        instruction_mapper::execute_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.sp, 0xFFFC);
+        assert_eq!(memory.addresses[0xFFFC..0xFFFE], [0x34, 0x12]);
+        instruction_mapper::execute_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.get_de(), 0x1234);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.pc, 0xC002);
+    }
+
+    #[test]
+    fn ret_returns_to_a_pushed_address() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        // PUSH BC; RET with BC pointing at a NOP
+        memory.addresses[0xC000..0xC002].copy_from_slice(&[0xC5, 0xC9]);
+        memory.addresses[0xC123] = 0x00;
+        cpu.pc = 0xC000;
+        cpu.sp = 0xFFFE;
+        cpu.registers.set_bc(0xC123);
+        instruction_mapper::execute_instruction(&mut cpu, &mut memory);
+        instruction_mapper::execute_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.pc, 0xC123);
+        assert_eq!(cpu.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn pop_reads_the_return_address_of_call() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        // CALL 0xC010; at 0xC010 POP HL
+        memory.addresses[0xC000..0xC003].copy_from_slice(&[0xCD, 0x10, 0xC0]);
+        memory.addresses[0xC010] = 0xE1;
+        cpu.pc = 0xC000;
+        cpu.sp = 0xFFFE;
+        instruction_mapper::execute_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.pc, 0xC010);
+        instruction_mapper::execute_instruction(&mut cpu, &mut memory);
+        assert_eq!(cpu.registers.get_hl(), 0xC003);
+        assert_eq!(cpu.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn rst_pushes_the_next_instruction() {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        // RST 0x28
+        memory.addresses[0xC000] = 0xEF;
+        cpu.pc = 0xC000;
+        cpu.sp = 0xFFFE;
+        assert_eq!(instruction_mapper::execute_instruction(&mut cpu, &mut memory), 4);
+        assert_eq!(cpu.pc, 0x0028);
+        assert_eq!(memory.addresses[0xFFFC..0xFFFE], [0x01, 0xC0]);
+    }
+}
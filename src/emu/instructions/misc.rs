@@ -10,18 +10,20 @@ pub fn stop(cpu: &mut CPU, memory: &Memory) {
     cpu.pc += 2;
 }
 
+// The CPU sleeps until IE & IF is non zero, which also wakes it when IME is off
 pub fn halt(cpu: &mut CPU) {
-    // TODO
+    cpu.halted = true;
     cpu.pc += 1;
 }
 
 pub fn di(cpu: &mut CPU) {
-    // TODO
+    cpu.ime = false;
+    cpu.ime_pending = false;
     cpu.pc += 1;
 }
 
 pub fn ei(cpu: &mut CPU) {
-    // TODO
+    cpu.ime_pending = true;
     cpu.pc += 1;
 }
 
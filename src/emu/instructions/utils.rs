@@ -1,4 +1,6 @@
 use crate::emu::cpu::CPU;
+use crate::emu::io::ppu::oam_bug;
+use crate::emu::io::{dma, joypad, ppu, timer};
 use crate::emu::memory::Memory;
 
 pub fn get_next_bytes_little_endian(cpu: &CPU, memory: &Memory) -> u16 {
@@ -13,12 +15,91 @@ pub fn get_e8(cpu: &CPU, memory: &Memory) -> i8 {
 }
 
 pub fn read_byte_from_memory(memory: &Memory, address: usize) -> u8 {
+    if let Some(value) = dma::get_conflicting_read(memory, address).or_else(|| ppu::get_locked_read(memory, address)) {
+        return value;
+    }
+    read_byte_from_bus(memory, address)
+}
+
+// Reads without the restrictions the CPU is under, for use by other bus masters
+pub fn read_byte_from_bus(memory: &Memory, address: usize) -> u8 {
+    if let Some(ref cartridge) = memory.cartridge {
+        cartridge.mapper.observe_address(address);
+    }
+    peek_byte(memory, address)
+}
+
+// Reads what is mapped at `address` without the access being seen by the cartridge mapper
+pub fn peek_byte(memory: &Memory, address: usize) -> u8 {
+    if let Some(ref boot_rom) = memory.boot_rom
+        && address < boot_rom.len()
+        && !(0x0100..0x0200).contains(&address)
+    {
+        return boot_rom[address];
+    }
+    if let Some(ref cartridge) = memory.cartridge {
+        match address {
+            0x0000..=0x7FFF => return cartridge.read_rom(address),
+            0xA000..=0xBFFF => return cartridge.read_ram(address),
+            _ => (),
+        }
+    }
     if address >= 0xE000 && address < 0xFE00 {
         return memory.addresses[address - 0x2000];
     }
+    if address == 0xFF00 {
+        return joypad::read(memory);
+    }
     return memory.addresses[address];
 }
 
 pub fn write_byte_to_memory(memory: &mut Memory, address: usize, value: u8) {
+    if dma::blocks_write(memory, address) || ppu::blocks_write(memory, address) {
+        return;
+    }
+    match address {
+        0xFF00 => return joypad::write(memory, value),
+        0xFF04..=0xFF07 => return timer::write_register(memory, address, value),
+        0xFF40 => return ppu::write_lcdc(memory, value),
+        0xFF41 => return ppu::write_stat(memory, value),
+        // LY is read only
+        0xFF44 => return,
+        0xFF45 => return ppu::write_lyc(memory, value),
+        0xFF46 => dma::start(memory, value),
+        // any non zero write unmaps the boot ROM for good
+        0xFF50 if value != 0 => memory.boot_rom = None,
+        _ => (),
+    }
+    if let Some(ref mut cartridge) = memory.cartridge {
+        cartridge.mapper.observe_address(address);
+        match address {
+            0x0000..=0x7FFF => return cartridge.write_rom(address, value),
+            0xA000..=0xBFFF => return cartridge.write_ram(address, value),
+            _ => (),
+        }
+    }
     memory.addresses[address] = value;
 }
+
+// Every stack access (PUSH/POP, CALL/RET, RST and interrupts) shares this layout:
+// SP is decremented first and the high byte ends up at the higher address.
+// `cycles_left` is how many M-cycles of the instruction follow the last write.
+pub fn push_word(cpu: &mut CPU, memory: &mut Memory, value: u16, cycles_left: u8) {
+    // the decrement before the first write, then one for each write
+    for cycle in 0..3 {
+        oam_bug::corrupt_write(memory, cpu.sp.wrapping_sub(cycle), cycles_left + 2 - cycle as u8);
+    }
+    let [low, high] = value.to_le_bytes();
+    cpu.sp = cpu.sp.wrapping_sub(2);
+    write_byte_to_memory(memory, cpu.sp.wrapping_add(1) as usize, high);
+    write_byte_to_memory(memory, cpu.sp as usize, low);
+}
+
+pub fn pop_word(cpu: &mut CPU, memory: &mut Memory, cycles_left: u8) -> u16 {
+    oam_bug::corrupt_read_increase(memory, cpu.sp, cycles_left + 1);
+    oam_bug::corrupt_read_increase(memory, cpu.sp.wrapping_add(1), cycles_left);
+    let low = read_byte_from_memory(memory, cpu.sp as usize);
+    let high = read_byte_from_memory(memory, cpu.sp.wrapping_add(1) as usize);
+    cpu.sp = cpu.sp.wrapping_add(2);
+    u16::from_le_bytes([low, high])
+}
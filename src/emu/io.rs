@@ -1 +1,7 @@
+#[cfg(feature = "sdl")]
 pub mod display;
+pub mod dma;
+pub mod interrupt;
+pub mod joypad;
+pub mod ppu;
+pub mod timer;
@@ -1,152 +1,101 @@
-use crate::emu::cpu::CPU;
-use crate::emu::instruction_mapper;
-use crate::emu::memory::Memory;
+use std::thread;
+use std::time::Instant;
+
+use crate::emu::frame_limiter::FrameLimiter;
+use crate::emu::framebuffer::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::emu::io::joypad::Buttons;
+use crate::emu::io::ppu::Renderer;
+use crate::emu::model::Model;
+use crate::gameboy::GameBoy;
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Point;
-use sdl2::render::Canvas;
-use sdl2::sys::SDL_Point;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 
-enum ColorPalette {
-    Green,
-    LightGreen,
-    DarkGreen,
-    Black,
-}
-
-pub struct Display {
-    pixels: Vec<usize>,
-}
-
-impl Default for Display {
-    fn default() -> Self {
-        Self { pixels: initialize_vram() }
-    }
-}
+const INITIAL_SCALE: u32 = 3;
 
-fn initialize_vram() -> Vec<usize> {
-    let len: usize = 32 * 8 * 32 * 8;
-    vec![0; len]
-}
+// SDL window frontend, shows the framebuffer after every frame
+#[derive(Default)]
+pub struct Display {}
 
 impl Display {
-    fn construct_vram_content(&mut self, memory: &Memory) {
-        let tile_start_index: u16 = 0x8000;
-        let tile_diff: u16 = 0x0010;
-        let row_diff: usize = 32 * 8;
-        let mut tile_index: usize = 0;
-        for address in 0x9800..=0x9BFF {
-            let tile_id = memory.addresses[address as usize];
-            let index: u16 = tile_start_index + (tile_diff * tile_id as u16);
-            for i in (0..16).step_by(2) {
-                let upper = memory.addresses[index as usize + i];
-                let lower = memory.addresses[index as usize + i + 1];
-                for j in 0..8 {
-                    let color = get_bit_at_pos(upper, j) << 1 | get_bit_at_pos(lower, j);
-                    let pixel_position = (tile_index / 32) * row_diff * 8 + ((tile_index % 32) * 8) + ((i / 2) * row_diff) + 7 - j as usize;
-                    self.pixels[pixel_position] = color as usize;
-                }
-            }
-            tile_index += 1;
-        }
-    }
-
-    pub fn start_main_process(&mut self, cpu: &mut CPU, memory: &mut Memory) {
-        start(self, cpu, memory);
+    pub fn start_main_process(&mut self, gameboy: &mut GameBoy) {
+        start(gameboy);
     }
 }
 
-fn get_bit_at_pos(byte: u8, pos: u8) -> u8 {
-    match pos {
-        0 => (byte & 0b00000001) >> 0,
-        1 => (byte & 0b00000010) >> 1,
-        2 => (byte & 0b00000100) >> 2,
-        3 => (byte & 0b00001000) >> 3,
-        4 => (byte & 0b00010000) >> 4,
-        5 => (byte & 0b00100000) >> 5,
-        6 => (byte & 0b01000000) >> 6,
-        7 => (byte & 0b10000000) >> 7,
-        _ => panic!("Byte index is out of range"),
-    }
-}
-
-fn map_palette(value: &usize) -> ColorPalette {
-    match value {
-        0 => ColorPalette::Green,
-        1 => ColorPalette::LightGreen,
-        2 => ColorPalette::DarkGreen,
-        3 => ColorPalette::Black,
-        _ => panic!("Invalid color value"),
-    }
-}
-
-fn get_color(color: ColorPalette) -> Color {
-    match color {
-        ColorPalette::Green => Color::RGB(155, 188, 15),
-        ColorPalette::LightGreen => Color::RGB(139, 172, 15),
-        ColorPalette::DarkGreen => Color::RGB(48, 98, 48),
-        ColorPalette::Black => Color::RGB(15, 56, 15),
-    }
-}
-
-fn start(display: &mut Display, cpu: &mut CPU, memory: &mut Memory) {
-    let width: u32 = 160;
-    let height: u32 = 144;
-    let scale = 3;
+fn start(gameboy: &mut GameBoy) {
+    let width = SCREEN_WIDTH as u32;
+    let height = SCREEN_HEIGHT as u32;
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
-    let window = video_subsystem.window("Redox Damage", width * scale, height * scale).position_centered().build().unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
+    let window = video_subsystem.window("Redox Damage", width * INITIAL_SCALE, height * INITIAL_SCALE).position_centered().resizable().build().unwrap();
+    // vsync only keeps the picture from tearing, the speed comes from the frame limiter
+    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    // the renderer scales the screen by whole multiples and letterboxes the rest of the window
+    canvas.set_logical_size(width, height).unwrap();
+    canvas.set_integer_scale(true).unwrap();
+    canvas.set_draw_color(Color::BLACK);
 
-    canvas.set_draw_color(get_color(ColorPalette::Green));
-    canvas.clear();
-    canvas.present();
-    let _ = canvas.set_scale(scale as f32, scale as f32);
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGBA32, width, height).unwrap();
 
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut count = 0;
+    // debug view of the color indices before the palettes are applied
+    let mut show_color_index = false;
+    let mut buttons = Buttons::default();
+    let mut frame_limiter = FrameLimiter::new(Instant::now());
     'running: loop {
-        if count == 0 {
-            render(display, &memory, &mut canvas);
-        }
+        let cycles = gameboy.run_frame();
+        render(gameboy.framebuffer(), gameboy.model(), &mut canvas, &mut texture, show_color_index);
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => show_color_index = !show_color_index,
+                Event::KeyDown { keycode: Some(Keycode::F2), .. } => toggle_renderer(gameboy),
+                Event::KeyDown { keycode: Some(keycode), .. } => set_button(&mut buttons, keycode, true),
+                Event::KeyUp { keycode: Some(keycode), .. } => set_button(&mut buttons, keycode, false),
                 _ => {}
             }
         }
-        instruction_mapper::execute_instruction(cpu, memory);
-        update_ly_register(memory, count);
-        println!("executing {:2X} at {:2X}", memory.addresses[cpu.pc as usize], cpu.pc);
-        count = (count + 1) % 2000;
+        gameboy.set_buttons(buttons);
+        thread::sleep(frame_limiter.get_delay(cycles, Instant::now()));
     }
 }
 
-fn render(display: &mut Display, memory: &Memory, canvas: &mut Canvas<Window>) {
-    let mut i = 0;
-    let line_width = 32 * 8;
-    display.construct_vram_content(memory);
-    for pixel in display.pixels.iter() {
-        canvas.set_draw_color(get_color(map_palette(&pixel)));
-        let sdl_point: SDL_Point = SDL_Point { x: i % line_width, y: i / line_width };
-        let point: Point = Point::from_ll(sdl_point);
-        let _ = canvas.draw_point(point).expect("Render error during drawing pixel");
-        i = (i + 1) % display.pixels.len() as i32;
-    }
-    canvas.present();
+// arrow keys for the d-pad, X and Z for A and B, Enter for Start and Backspace for Select
+fn set_button(buttons: &mut Buttons, keycode: Keycode, pressed: bool) {
+    let button = match keycode {
+        Keycode::Right => &mut buttons.right,
+        Keycode::Left => &mut buttons.left,
+        Keycode::Up => &mut buttons.up,
+        Keycode::Down => &mut buttons.down,
+        Keycode::X => &mut buttons.a,
+        Keycode::Z => &mut buttons.b,
+        Keycode::Backspace => &mut buttons.select,
+        Keycode::Return => &mut buttons.start,
+        _ => return,
+    };
+    *button = pressed;
 }
 
-fn update_ly_register(memory: &mut Memory, index: i32) {
-    if index % 10 == 0 {
-        memory.addresses[0xFF44] += 1;
+fn toggle_renderer(gameboy: &mut GameBoy) {
+    let renderer = match gameboy.renderer() {
+        Renderer::Scanline => Renderer::Fifo,
+        Renderer::Fifo => Renderer::Scanline,
     };
+    gameboy.set_renderer(renderer);
+    eprintln!("Renderer: {}", renderer.name());
+}
 
-    if memory.addresses[0xFF44] >= 154 {
-        memory.addresses[0xFF44] = 0;
-    };
+// Writes the frame straight into the streaming texture and lets the renderer scale it to the window
+fn render(framebuffer: &Framebuffer, model: Model, canvas: &mut Canvas<Window>, texture: &mut Texture, show_color_index: bool) {
+    texture.with_lock(None, |buffer, pitch| framebuffer.write_rgba(buffer, pitch, model, show_color_index)).expect("Render error during texture upload");
+    canvas.clear();
+    canvas.copy(texture, None, None).expect("Render error during drawing frame");
+    canvas.present();
 }
@@ -0,0 +1,113 @@
+use crate::emu::instructions::utils;
+use crate::emu::memory::Memory;
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+pub const OAM_START: usize = 0xFE00;
+const TRANSFER_LEN: u8 = 0xA0;
+// M-cycles between the write to 0xFF46 and the first byte being copied
+const STARTUP_DELAY: u8 = 1;
+
+#[derive(Debug, Default, Clone)]
+pub struct Dma {
+    active: bool,
+    source: u16,
+    index: u8,
+    // a (re)started transfer only takes over once its startup delay has passed,
+    // until then a running transfer keeps going
+    pending: Option<(u16, u8)>,
+    // byte currently on the bus, this is what conflicting CPU reads see
+    bus_value: u8,
+}
+
+impl Dma {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn is_on_video_bus(&self) -> bool {
+        (0x8000..0xA000).contains(&self.source)
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.active);
+        writer.write_u16(self.source);
+        writer.write_u8(self.index);
+        writer.write_bool(self.pending.is_some());
+        let (source, delay) = self.pending.unwrap_or_default();
+        writer.write_u16(source);
+        writer.write_u8(delay);
+        writer.write_u8(self.bus_value);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.active = reader.read_bool()?;
+        self.source = reader.read_u16()?;
+        self.index = reader.read_u8()?;
+        let has_pending = reader.read_bool()?;
+        let pending = (reader.read_u16()?, reader.read_u8()?);
+        self.pending = has_pending.then_some(pending);
+        self.bus_value = reader.read_u8()?;
+        if self.active && self.index >= TRANSFER_LEN {
+            return Err(StateError::Invalid);
+        }
+        Ok(())
+    }
+}
+
+pub fn start(memory: &mut Memory, value: u8) {
+    // sources above 0xDFFF are fed from the work RAM behind the echo area
+    let source = if value >= 0xE0 { (value as u16 - 0x20) << 8 } else { (value as u16) << 8 };
+    memory.dma.pending = Some((source, STARTUP_DELAY));
+}
+
+// Advances the transfer by one M-cycle, copying one byte into OAM
+pub fn tick(memory: &mut Memory) {
+    if let Some((source, delay)) = memory.dma.pending {
+        if delay == 0 {
+            memory.dma.pending = None;
+            memory.dma.active = true;
+            memory.dma.source = source;
+            memory.dma.index = 0;
+        } else {
+            memory.dma.pending = Some((source, delay - 1));
+        }
+    }
+    if !memory.dma.active {
+        return;
+    }
+    let index = memory.dma.index;
+    let value = utils::read_byte_from_bus(memory, (memory.dma.source + index as u16) as usize);
+    memory.addresses[OAM_START + index as usize] = value;
+    memory.dma.bus_value = value;
+    memory.dma.index += 1;
+    if memory.dma.index == TRANSFER_LEN {
+        memory.dma.active = false;
+    }
+}
+
+// While a transfer runs the CPU only has HRAM and the IO registers to itself.
+// OAM reads return 0xFF, reads from the bus the DMA is using return the byte being copied.
+pub fn get_conflicting_read(memory: &Memory, address: usize) -> Option<u8> {
+    if !memory.dma.is_active() || address >= 0xFF00 {
+        return None;
+    }
+    if (OAM_START..0xFF00).contains(&address) {
+        return Some(0xFF);
+    }
+    let video_bus = (0x8000..0xA000).contains(&address);
+    if video_bus == memory.dma.is_on_video_bus() {
+        return Some(memory.dma.bus_value);
+    }
+    None
+}
+
+// CPU writes that collide with the transfer are dropped
+pub fn blocks_write(memory: &Memory, address: usize) -> bool {
+    if !memory.dma.is_active() || address >= 0xFF00 {
+        return false;
+    }
+    if (OAM_START..0xFF00).contains(&address) {
+        return true;
+    }
+    (0x8000..0xA000).contains(&address) == memory.dma.is_on_video_bus()
+}
@@ -0,0 +1,193 @@
+use crate::emu::cpu::CPU;
+use crate::emu::instructions::utils;
+use crate::emu::memory::Memory;
+
+pub const IF: usize = 0xFF0F;
+pub const IE: usize = 0xFFFF;
+const VECTOR_BASE: u16 = 0x0040;
+pub const DISPATCH_CYCLES: u8 = 5;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Joypad,
+}
+
+impl Interrupt {
+    fn get_bit(self) -> u8 {
+        match self {
+            Interrupt::VBlank => 0b00001,
+            Interrupt::Stat => 0b00010,
+            Interrupt::Timer => 0b00100,
+            Interrupt::Joypad => 0b10000,
+        }
+    }
+}
+
+pub fn request(memory: &mut Memory, interrupt: Interrupt) {
+    memory.addresses[IF] |= interrupt.get_bit();
+}
+
+// Interrupts that are both requested and enabled
+pub fn get_pending(memory: &Memory) -> u8 {
+    memory.addresses[IE] & memory.addresses[IF] & 0x1F
+}
+
+// Calls the handler of the highest priority pending interrupt, which takes 5 M-cycles:
+// two wait states, the PC push and the jump. The interrupt is only picked after the high byte
+// of PC was pushed, so a push that overwrites IE can cancel it and the CPU ends up at 0x0000.
+pub fn dispatch(cpu: &mut CPU, memory: &mut Memory) -> u8 {
+    cpu.ime = false;
+    memory.tick(DISPATCH_CYCLES - 1);
+    utils::push_word(cpu, memory, cpu.pc, 0);
+    cpu.pc = match get_pending(memory) {
+        0 => 0x0000,
+        pending => {
+            let bit = pending.trailing_zeros();
+            memory.addresses[IF] &= !(1 << bit);
+            VECTOR_BASE + bit as u16 * 8
+        }
+    };
+    memory.tick(1);
+    DISPATCH_CYCLES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::instruction_mapper;
+
+    fn setup(code: &[u8], ie: u8) -> (CPU, Memory) {
+        let mut cpu = CPU::default();
+        let mut memory = Memory::default();
+        memory.addresses[0xC000..0xC000 + code.len()].copy_from_slice(code);
+        memory.addresses[IE] = ie;
+        memory.addresses[IF] = 0xE0;
+        cpu.pc = 0xC000;
+        cpu.sp = 0xFFFE;
+        (cpu, memory)
+    }
+
+    fn step(cpu: &mut CPU, memory: &mut Memory) -> u8 {
+        instruction_mapper::execute_instruction(cpu, memory)
+    }
+
+    #[test]
+    fn dispatch_pushes_pc_and_jumps_to_the_vector() {
+        let (mut cpu, mut memory) = setup(&[0x00], 0x1F);
+        cpu.ime = true;
+        request(&mut memory, Interrupt::Timer);
+        assert_eq!(step(&mut cpu, &mut memory), DISPATCH_CYCLES);
+        assert_eq!(cpu.pc, 0x0050);
+        assert!(!cpu.ime);
+        assert_eq!(memory.addresses[IF], 0xE0);
+        assert_eq!(cpu.sp, 0xFFFC);
+        assert_eq!(memory.addresses[0xFFFC..0xFFFE], [0x00, 0xC0]);
+    }
+
+    #[test]
+    fn lowest_bit_has_priority() {
+        let (mut cpu, mut memory) = setup(&[0x00], 0x1F);
+        cpu.ime = true;
+        request(&mut memory, Interrupt::Joypad);
+        request(&mut memory, Interrupt::Stat);
+        step(&mut cpu, &mut memory);
+        assert_eq!(cpu.pc, 0x0048);
+        assert_eq!(memory.addresses[IF], 0xF0);
+
+        // disabled interrupts are skipped
+        let (mut cpu, mut memory) = setup(&[0x00], 0x10);
+        cpu.ime = true;
+        request(&mut memory, Interrupt::VBlank);
+        request(&mut memory, Interrupt::Joypad);
+        step(&mut cpu, &mut memory);
+        assert_eq!(cpu.pc, 0x0060);
+    }
+
+    #[test]
+    fn ei_takes_effect_after_the_next_instruction() {
+        // EI; NOP; NOP
+        let (mut cpu, mut memory) = setup(&[0xFB, 0x00, 0x00], 0x01);
+        request(&mut memory, Interrupt::VBlank);
+        step(&mut cpu, &mut memory);
+        assert!(!cpu.ime);
+        step(&mut cpu, &mut memory);
+        assert_eq!(cpu.pc, 0xC002);
+        assert!(cpu.ime);
+        step(&mut cpu, &mut memory);
+        assert_eq!(cpu.pc, 0x0040);
+        assert_eq!(memory.addresses[0xFFFC..0xFFFE], [0x02, 0xC0]);
+    }
+
+    #[test]
+    fn di_cancels_a_pending_ei() {
+        // EI; DI; NOP
+        let (mut cpu, mut memory) = setup(&[0xFB, 0xF3, 0x00], 0x01);
+        request(&mut memory, Interrupt::VBlank);
+        for _ in 0..3 {
+            step(&mut cpu, &mut memory);
+        }
+        assert_eq!(cpu.pc, 0xC003);
+        assert!(!cpu.ime);
+    }
+
+    #[test]
+    fn reti_enables_interrupts_immediately() {
+        // RETI to 0xC001, which holds a NOP
+        let (mut cpu, mut memory) = setup(&[0xD9, 0x00], 0x01);
+        cpu.sp = 0xFFFC;
+        memory.addresses[0xFFFC..0xFFFE].copy_from_slice(&[0x01, 0xC0]);
+        request(&mut memory, Interrupt::VBlank);
+        step(&mut cpu, &mut memory);
+        assert!(cpu.ime);
+        assert_eq!(cpu.pc, 0xC001);
+        step(&mut cpu, &mut memory);
+        assert_eq!(cpu.pc, 0x0040);
+    }
+
+    #[test]
+    fn halt_wakes_up_without_ime() {
+        // HALT; INC A
+        let (mut cpu, mut memory) = setup(&[0x76, 0x3C], 0x04);
+        step(&mut cpu, &mut memory);
+        assert!(cpu.halted);
+        assert_eq!(step(&mut cpu, &mut memory), 1);
+        assert_eq!(cpu.pc, 0xC001);
+        // a requested but disabled interrupt does not wake it up
+        request(&mut memory, Interrupt::VBlank);
+        step(&mut cpu, &mut memory);
+        assert!(cpu.halted);
+        request(&mut memory, Interrupt::Timer);
+        step(&mut cpu, &mut memory);
+        assert!(!cpu.halted);
+        assert_eq!((cpu.pc, cpu.registers.a), (0xC002, 1));
+        // not serviced, the request is still there
+        assert_eq!(memory.addresses[IF], 0xE5);
+    }
+
+    #[test]
+    fn halt_wakes_up_into_the_handler() {
+        let (mut cpu, mut memory) = setup(&[0x76, 0x00], 0x04);
+        cpu.ime = true;
+        step(&mut cpu, &mut memory);
+        request(&mut memory, Interrupt::Timer);
+        assert_eq!(step(&mut cpu, &mut memory), DISPATCH_CYCLES);
+        assert_eq!(cpu.pc, 0x0050);
+        assert_eq!(memory.addresses[0xFFFC..0xFFFE], [0x01, 0xC0]);
+    }
+
+    #[test]
+    fn pushing_over_ie_cancels_the_dispatch() {
+        let (mut cpu, mut memory) = setup(&[0x00], 0x01);
+        cpu.ime = true;
+        // the high byte of PC (0xC0) lands in IE and disables every interrupt
+        cpu.sp = 0x0000;
+        request(&mut memory, Interrupt::VBlank);
+        step(&mut cpu, &mut memory);
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(memory.addresses[IE], 0xC0);
+        assert_eq!(memory.addresses[IF], 0xE1);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::emu::io::interrupt::{self, Interrupt};
+use crate::emu::memory::Memory;
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+const SELECT_DIRECTIONS: u8 = 0b00010000;
+const SELECT_BUTTONS: u8 = 0b00100000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Buttons {
+    pub right: bool,
+    pub left: bool,
+    pub up: bool,
+    pub down: bool,
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+}
+
+impl Buttons {
+    // lower nibble of P1 for the directions or the buttons, a pressed key reads as 0
+    fn get_bits(&self, directions: bool) -> u8 {
+        let keys = if directions {
+            [self.right, self.left, self.up, self.down]
+        } else {
+            [self.a, self.b, self.select, self.start]
+        };
+        keys.iter().enumerate().fold(0x0F, |bits, (i, pressed)| if *pressed { bits & !(1 << i) } else { bits })
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Joypad {
+    buttons: Buttons,
+    // bits 4 and 5 of P1 as last written, 0 selects the group
+    select: u8,
+}
+
+impl Joypad {
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        let buttons = &self.buttons;
+        for pressed in [buttons.right, buttons.left, buttons.up, buttons.down, buttons.a, buttons.b, buttons.select, buttons.start] {
+            writer.write_bool(pressed);
+        }
+        writer.write_u8(self.select);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let buttons = &mut self.buttons;
+        for pressed in [
+            &mut buttons.right,
+            &mut buttons.left,
+            &mut buttons.up,
+            &mut buttons.down,
+            &mut buttons.a,
+            &mut buttons.b,
+            &mut buttons.select,
+            &mut buttons.start,
+        ] {
+            *pressed = reader.read_bool()?;
+        }
+        self.select = reader.read_u8()? & (SELECT_DIRECTIONS | SELECT_BUTTONS);
+        Ok(())
+    }
+}
+
+pub fn read(memory: &Memory) -> u8 {
+    let joypad = &memory.joypad;
+    let mut bits = 0x0F;
+    if joypad.select & SELECT_DIRECTIONS == 0 {
+        bits &= joypad.buttons.get_bits(true);
+    }
+    if joypad.select & SELECT_BUTTONS == 0 {
+        bits &= joypad.buttons.get_bits(false);
+    }
+    0xC0 | joypad.select | bits
+}
+
+pub fn write(memory: &mut Memory, value: u8) {
+    memory.joypad.select = value & (SELECT_DIRECTIONS | SELECT_BUTTONS);
+}
+
+// Pressing a key of a selected group pulls its P1 line low, which requests the joypad interrupt
+pub fn set_buttons(memory: &mut Memory, buttons: Buttons) {
+    let before = read(memory);
+    memory.joypad.buttons = buttons;
+    if before & !read(memory) & 0x0F > 0 {
+        interrupt::request(memory, Interrupt::Joypad);
+    }
+}
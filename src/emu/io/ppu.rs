@@ -0,0 +1,804 @@
+mod fifo;
+pub mod oam_bug;
+mod object;
+mod scanline;
+
+use crate::emu::framebuffer::Framebuffer;
+use crate::emu::io::interrupt::{self, Interrupt};
+use crate::emu::memory::Memory;
+use crate::emu::state::{StateError, StateReader, StateWriter};
+use fifo::Fifo;
+use object::Object;
+
+pub const LCDC: usize = 0xFF40;
+pub const STAT: usize = 0xFF41;
+pub const SCY: usize = 0xFF42;
+pub const SCX: usize = 0xFF43;
+pub const LY: usize = 0xFF44;
+pub const LYC: usize = 0xFF45;
+pub const BGP: usize = 0xFF47;
+pub const WY: usize = 0xFF4A;
+pub const WX: usize = 0xFF4B;
+
+pub const DOTS_PER_LINE: u16 = 456;
+pub const LINES_PER_FRAME: u8 = 154;
+pub const VISIBLE_LINES: u8 = 144;
+pub const OAM_SCAN_DOTS: u16 = 80;
+const DRAWING_DOTS: u16 = 172;
+// LY already reads 0 for most of the last line
+const LAST_LINE_LY_DOTS: u16 = 4;
+
+pub const LCDC_BG_ENABLE: u8 = 0b00000001;
+pub const LCDC_OBJECT_ENABLE: u8 = 0b00000010;
+pub const LCDC_BG_TILE_MAP: u8 = 0b00001000;
+pub const LCDC_TILE_DATA: u8 = 0b00010000;
+pub const LCDC_WINDOW_ENABLE: u8 = 0b00100000;
+pub const LCDC_WINDOW_TILE_MAP: u8 = 0b01000000;
+pub const LCDC_ENABLE: u8 = 0b10000000;
+
+const STAT_COINCIDENCE: u8 = 0b00000100;
+const STAT_HBLANK_ENABLE: u8 = 0b00001000;
+const STAT_VBLANK_ENABLE: u8 = 0b00010000;
+const STAT_OAM_ENABLE: u8 = 0b00100000;
+const STAT_LYC_ENABLE: u8 = 0b01000000;
+const STAT_WRITABLE: u8 = 0b01111000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    HBlank,
+    VBlank,
+    #[default]
+    OamScan,
+    Drawing,
+}
+
+impl Mode {
+    // value of the two lowest STAT bits
+    pub fn get_bits(self) -> u8 {
+        match self {
+            Mode::HBlank => 0,
+            Mode::VBlank => 1,
+            Mode::OamScan => 2,
+            Mode::Drawing => 3,
+        }
+    }
+
+    pub fn from_bits(bits: u8) -> Mode {
+        match bits & 0b11 {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::OamScan,
+            _ => Mode::Drawing,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    // draws each line in one go at the end of mode 3, fast but blind to mid line register writes
+    #[default]
+    Scanline,
+    // shifts out one pixel per dot like the hardware, mode 3 length varies with SCX, the window and objects
+    Fifo,
+}
+
+impl Renderer {
+    pub const ALL: [Renderer; 2] = [Renderer::Scanline, Renderer::Fifo];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Renderer::Scanline => "scanline",
+            Renderer::Fifo => "fifo",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Renderer> {
+        Renderer::ALL.into_iter().find(|renderer| renderer.name() == name.to_ascii_lowercase())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ppu {
+    // position within the current line
+    dot: u16,
+    // line being processed, differs from LY on the last line of the frame
+    line: u8,
+    mode: Mode,
+    // all STAT interrupt sources ORed together, the interrupt is only requested on its rising edge
+    stat_line: bool,
+    frame_complete: bool,
+    // the first frame after the LCD is switched on is not shown
+    blank_frame: bool,
+    // set once LY matched WY during the frame, the window can only show up after that
+    window_triggered: bool,
+    // window line to draw next, only advances on lines the window was actually drawn on
+    window_line: u8,
+    window_wraps: bool,
+    // objects found by the OAM scan of the current line
+    objects: Vec<Object>,
+    framebuffer: Framebuffer,
+    renderer: Renderer,
+    // renderer drawing the current line, a switch only takes effect on the next one
+    line_renderer: Renderer,
+    fifo: Fifo,
+    // keeps the CPU out of VRAM and OAM while the PPU uses them, can be turned off for debugging
+    access_locks: bool,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self {
+            dot: 0,
+            line: 0,
+            mode: Mode::default(),
+            stat_line: false,
+            frame_complete: false,
+            blank_frame: false,
+            window_triggered: false,
+            window_line: 0,
+            window_wraps: false,
+            objects: Vec::new(),
+            framebuffer: Framebuffer::default(),
+            renderer: Renderer::default(),
+            line_renderer: Renderer::default(),
+            fifo: Fifo::default(),
+            access_locks: true,
+        }
+    }
+}
+
+impl Ppu {
+    pub fn get_framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn get_renderer(&self) -> Renderer {
+        self.renderer
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    pub fn set_access_locks(&mut self, enabled: bool) {
+        self.access_locks = enabled;
+    }
+
+    // Returns true once per frame when VBlank has been entered since the last call
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
+    // The renderer and the access locks are settings of the frontend and stay as they are
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.dot);
+        writer.write_u8(self.line);
+        writer.write_u8(self.mode.get_bits());
+        for value in [self.stat_line, self.frame_complete, self.blank_frame, self.window_triggered] {
+            writer.write_bool(value);
+        }
+        writer.write_u8(self.window_line);
+        writer.write_bool(self.window_wraps);
+        writer.write_u8(self.objects.len() as u8);
+        for object in &self.objects {
+            writer.write_u16(object.y as u16);
+            writer.write_u16(object.x as u16);
+            writer.write_u8(object.tile);
+            writer.write_u8(object.attributes);
+        }
+        self.framebuffer.write_state(writer);
+        writer.write_bool(self.line_renderer == Renderer::Fifo);
+        self.fifo.write_state(writer);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.dot = reader.read_u16()?;
+        self.line = reader.read_u8()?;
+        if self.dot >= DOTS_PER_LINE || self.line >= LINES_PER_FRAME {
+            return Err(StateError::Invalid);
+        }
+        self.mode = Mode::from_bits(reader.read_u8()?);
+        self.stat_line = reader.read_bool()?;
+        self.frame_complete = reader.read_bool()?;
+        self.blank_frame = reader.read_bool()?;
+        self.window_triggered = reader.read_bool()?;
+        self.window_line = reader.read_u8()?;
+        self.window_wraps = reader.read_bool()?;
+        let len = reader.read_u8()?;
+        self.objects = (0..len)
+            .map(|_| {
+                Ok(Object {
+                    y: reader.read_u16()? as i16,
+                    x: reader.read_u16()? as i16,
+                    tile: reader.read_u8()?,
+                    attributes: reader.read_u8()?,
+                })
+            })
+            .collect::<Result<_, StateError>>()?;
+        self.framebuffer.read_state(reader)?;
+        self.line_renderer = if reader.read_bool()? { Renderer::Fifo } else { Renderer::Scanline };
+        self.fifo.read_state(reader)
+    }
+}
+
+// Advances the PPU by one M-cycle, which is 4 dots
+pub fn tick(memory: &mut Memory) {
+    for _ in 0..4 {
+        step_dot(memory);
+    }
+}
+
+pub fn is_lcd_enabled(memory: &Memory) -> bool {
+    memory.addresses[LCDC] & LCDC_ENABLE > 0
+}
+
+fn step_dot(memory: &mut Memory) {
+    if !is_lcd_enabled(memory) {
+        return step_dot_lcd_off(memory);
+    }
+    memory.ppu.dot += 1;
+    if memory.ppu.dot == DOTS_PER_LINE {
+        memory.ppu.dot = 0;
+        memory.ppu.line = (memory.ppu.line + 1) % LINES_PER_FRAME;
+        memory.addresses[LY] = memory.ppu.line;
+    }
+    if memory.ppu.line == LINES_PER_FRAME - 1 && memory.ppu.dot == LAST_LINE_LY_DOTS {
+        memory.addresses[LY] = 0;
+    }
+    let current_mode = memory.ppu.mode;
+    let mode = match current_mode {
+        _ if memory.ppu.line >= VISIBLE_LINES => Mode::VBlank,
+        _ if memory.ppu.dot < OAM_SCAN_DOTS => Mode::OamScan,
+        Mode::OamScan => Mode::Drawing,
+        Mode::Drawing if advance_drawing(memory) => Mode::HBlank,
+        mode => mode,
+    };
+    if mode != memory.ppu.mode {
+        enter_mode(memory, mode);
+    }
+    update_stat(memory);
+}
+
+// With the LCD off a frame still completes every 154 lines, so the frontend keeps running
+fn step_dot_lcd_off(memory: &mut Memory) {
+    memory.ppu.dot += 1;
+    if memory.ppu.dot == DOTS_PER_LINE {
+        memory.ppu.dot = 0;
+        memory.ppu.line = (memory.ppu.line + 1) % LINES_PER_FRAME;
+        if memory.ppu.line == 0 {
+            memory.ppu.frame_complete = true;
+        }
+    }
+}
+
+fn enter_mode(memory: &mut Memory, mode: Mode) {
+    memory.ppu.mode = mode;
+    memory.addresses[STAT] = (memory.addresses[STAT] & !0b11) | mode.get_bits();
+    match mode {
+        Mode::OamScan => {
+            if memory.addresses[LY] == memory.addresses[WY] {
+                memory.ppu.window_triggered = true;
+            }
+            memory.ppu.objects = object::scan_oam(memory, memory.ppu.line);
+        }
+        Mode::Drawing => {
+            memory.ppu.line_renderer = memory.ppu.renderer;
+            if memory.ppu.line_renderer == Renderer::Fifo {
+                fifo::start_line(memory);
+            }
+        }
+        Mode::HBlank => match memory.ppu.line_renderer {
+            Renderer::Scanline => scanline::render_line(memory, memory.ppu.line),
+            Renderer::Fifo if memory.ppu.fifo.has_drawn_window() => memory.ppu.window_line += 1,
+            Renderer::Fifo => (),
+        },
+        Mode::VBlank => {
+            interrupt::request(memory, Interrupt::VBlank);
+            if std::mem::take(&mut memory.ppu.blank_frame) {
+                memory.ppu.framebuffer.clear();
+            }
+            memory.ppu.frame_complete = true;
+            memory.ppu.window_triggered = false;
+            memory.ppu.window_line = 0;
+            memory.ppu.window_wraps = false;
+        }
+    }
+}
+
+// Returns true once mode 3 of the current line is over
+fn advance_drawing(memory: &mut Memory) -> bool {
+    match memory.ppu.line_renderer {
+        Renderer::Scanline => memory.ppu.dot >= OAM_SCAN_DOTS + DRAWING_DOTS,
+        Renderer::Fifo => fifo::step(memory),
+    }
+}
+
+fn get_stat_line(memory: &Memory, stat: u8) -> bool {
+    let mode = memory.ppu.mode;
+    (stat & STAT_HBLANK_ENABLE > 0 && mode == Mode::HBlank)
+        || (stat & STAT_VBLANK_ENABLE > 0 && mode == Mode::VBlank)
+        // the OAM source also fires when VBlank starts
+        || (stat & STAT_OAM_ENABLE > 0 && (mode == Mode::OamScan || (mode == Mode::VBlank && memory.ppu.line == VISIBLE_LINES && memory.ppu.dot == 0)))
+        || (stat & STAT_LYC_ENABLE > 0 && stat & STAT_COINCIDENCE > 0)
+}
+
+fn set_stat_line(memory: &mut Memory, stat_line: bool) {
+    if stat_line && !memory.ppu.stat_line {
+        interrupt::request(memory, Interrupt::Stat);
+    }
+    memory.ppu.stat_line = stat_line;
+}
+
+// Refreshes the coincidence flag and raises the STAT interrupt on a rising edge of the combined sources
+fn update_stat(memory: &mut Memory) {
+    let mut stat = memory.addresses[STAT] & !STAT_COINCIDENCE;
+    if memory.addresses[LY] == memory.addresses[LYC] {
+        stat |= STAT_COINCIDENCE;
+    }
+    memory.addresses[STAT] = stat;
+    set_stat_line(memory, get_stat_line(memory, stat));
+}
+
+// Whether the CPU is locked out of `address` in the current mode,
+// VRAM is in use during mode 3 and OAM during modes 2 and 3
+fn is_locked(memory: &Memory, address: usize) -> bool {
+    if !memory.ppu.access_locks || !is_lcd_enabled(memory) {
+        return false;
+    }
+    match address {
+        0x8000..=0x9FFF => memory.ppu.mode == Mode::Drawing,
+        0xFE00..=0xFEFF => matches!(memory.ppu.mode, Mode::OamScan | Mode::Drawing),
+        _ => false,
+    }
+}
+
+// Locked VRAM and OAM read as 0xFF
+pub fn get_locked_read(memory: &Memory, address: usize) -> Option<u8> {
+    if is_locked(memory, address) { Some(0xFF) } else { None }
+}
+
+// CPU writes to locked VRAM and OAM are dropped
+pub fn blocks_write(memory: &Memory, address: usize) -> bool {
+    is_locked(memory, address)
+}
+
+pub fn write_lcdc(memory: &mut Memory, value: u8) {
+    let was_enabled = is_lcd_enabled(memory);
+    memory.addresses[LCDC] = value;
+    match (was_enabled, is_lcd_enabled(memory)) {
+        (true, false) => switch_lcd_off(memory),
+        (false, true) => switch_lcd_on(memory),
+        _ => (),
+    }
+}
+
+// LY is held at 0 and STAT reports mode 0 until the LCD is switched back on, the screen shows white
+fn switch_lcd_off(memory: &mut Memory) {
+    memory.ppu.dot = 0;
+    memory.ppu.line = 0;
+    memory.ppu.mode = Mode::HBlank;
+    memory.ppu.stat_line = false;
+    memory.addresses[LY] = 0;
+    memory.addresses[STAT] &= !0b11;
+    memory.ppu.framebuffer.clear();
+    memory.ppu.frame_complete = true;
+}
+
+fn switch_lcd_on(memory: &mut Memory) {
+    memory.ppu.dot = 0;
+    memory.ppu.line = 0;
+    memory.ppu.blank_frame = true;
+    memory.ppu.window_triggered = false;
+    memory.ppu.window_line = 0;
+    memory.ppu.window_wraps = false;
+    enter_mode(memory, Mode::OamScan);
+    update_stat(memory);
+}
+
+// Puts the running LCD on the last line of a frame where LY already reads 0, which is where the
+// boot ROM hands over to the game. The mode and coincidence bits of STAT follow from that.
+pub fn start_on_last_line(memory: &mut Memory, dot: u16) {
+    memory.ppu.dot = dot;
+    memory.ppu.line = LINES_PER_FRAME - 1;
+    memory.ppu.stat_line = false;
+    memory.addresses[LY] = if dot < LAST_LINE_LY_DOTS { LINES_PER_FRAME - 1 } else { 0 };
+    memory.ppu.mode = Mode::VBlank;
+    memory.addresses[STAT] = (memory.addresses[STAT] & !0b11) | Mode::VBlank.get_bits();
+    update_stat(memory);
+}
+
+pub fn write_stat(memory: &mut Memory, value: u8) {
+    let stat = memory.addresses[STAT];
+    // on pre-CGB models the write briefly enables every source,
+    // which fires the interrupt outside of mode 3 or when LY equals LYC
+    if !memory.model.is_cgb() {
+        set_stat_line(memory, get_stat_line(memory, stat | STAT_WRITABLE));
+    }
+    memory.addresses[STAT] = 0x80 | (value & STAT_WRITABLE) | (stat & 0b111);
+    update_stat(memory);
+}
+
+pub fn write_lyc(memory: &mut Memory, value: u8) {
+    memory.addresses[LYC] = value;
+    update_stat(memory);
+}
+
+pub fn get_bit_at_pos(byte: u8, pos: u8) -> u8 {
+    match pos {
+        0 => byte & 0b00000001,
+        1 => (byte & 0b00000010) >> 1,
+        2 => (byte & 0b00000100) >> 2,
+        3 => (byte & 0b00001000) >> 3,
+        4 => (byte & 0b00010000) >> 4,
+        5 => (byte & 0b00100000) >> 5,
+        6 => (byte & 0b01000000) >> 6,
+        7 => (byte & 0b10000000) >> 7,
+        _ => panic!("Byte index is out of range"),
+    }
+}
+
+// on the DMG clearing the BG enable bit hides the window as well
+pub fn is_window_enabled(memory: &Memory, lcdc: u8) -> bool {
+    lcdc & LCDC_WINDOW_ENABLE > 0 && lcdc & LCDC_BG_ENABLE > 0 && memory.ppu.window_triggered
+}
+
+// 0x8000 addressing uses unsigned tile ids, 0x8800 addressing signed ones relative to 0x9000
+pub fn get_tile_address(lcdc: u8, tile_id: u8) -> usize {
+    if lcdc & LCDC_TILE_DATA > 0 {
+        0x8000 + tile_id as usize * 16
+    } else {
+        (0x9000 + tile_id as i8 as isize * 16) as usize
+    }
+}
+
+// Looks up the shade a palette register assigns to a color index
+pub fn apply_palette(palette: u8, color_index: u8) -> u8 {
+    (palette >> (color_index * 2)) & 0b11
+}
+
+// A disabled background shows as shade 0, whatever BGP says
+pub fn get_bg_shade(memory: &Memory, lcdc: u8, color_index: u8) -> u8 {
+    if lcdc & LCDC_BG_ENABLE > 0 { apply_palette(memory.addresses[BGP], color_index) } else { 0 }
+}
+
+// Decodes the 2 bit color index of a pixel, each tile row is stored as a low and a high bit plane
+pub fn get_tile_color(memory: &Memory, tile_address: usize, x: u8, y: u8) -> u8 {
+    let low = memory.addresses[tile_address + y as usize * 2];
+    let high = memory.addresses[tile_address + y as usize * 2 + 1];
+    get_bit_at_pos(high, 7 - x) << 1 | get_bit_at_pos(low, 7 - x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::framebuffer::Pixel;
+    use crate::emu::instructions::utils;
+    use crate::emu::io::interrupt::IF;
+    use crate::emu::model::Model;
+
+    fn lcd_on() -> Memory {
+        let mut memory = Memory::default();
+        write_lcdc(&mut memory, LCDC_ENABLE | LCDC_BG_ENABLE);
+        memory.addresses[IF] = 0;
+        memory
+    }
+
+    // steps dot by dot until the PPU is at `dot` of `line`
+    fn run_to(memory: &mut Memory, line: u8, dot: u16) {
+        while memory.ppu.line != line || memory.ppu.dot != dot {
+            step_dot(memory);
+        }
+    }
+
+    fn stat_mode(memory: &Memory) -> u8 {
+        memory.addresses[STAT] & 0b11
+    }
+
+    #[test]
+    fn modes_follow_the_dot_counter() {
+        let mut memory = lcd_on();
+        assert_eq!(memory.ppu.mode, Mode::OamScan);
+        run_to(&mut memory, 0, OAM_SCAN_DOTS - 1);
+        assert_eq!(memory.ppu.mode, Mode::OamScan);
+        step_dot(&mut memory);
+        assert_eq!(memory.ppu.mode, Mode::Drawing);
+        assert_eq!(stat_mode(&memory), 3);
+        run_to(&mut memory, 0, OAM_SCAN_DOTS + DRAWING_DOTS - 1);
+        assert_eq!(memory.ppu.mode, Mode::Drawing);
+        step_dot(&mut memory);
+        assert_eq!(memory.ppu.mode, Mode::HBlank);
+        assert_eq!(stat_mode(&memory), 0);
+        run_to(&mut memory, 1, 0);
+        assert_eq!(memory.ppu.mode, Mode::OamScan);
+        assert_eq!(memory.addresses[LY], 1);
+    }
+
+    #[test]
+    fn vblank_starts_at_line_144() {
+        let mut memory = lcd_on();
+        run_to(&mut memory, VISIBLE_LINES - 1, DOTS_PER_LINE - 1);
+        assert_eq!(memory.addresses[IF], 0);
+        assert!(!memory.ppu.take_frame_complete());
+        step_dot(&mut memory);
+        assert_eq!(memory.ppu.mode, Mode::VBlank);
+        assert_eq!(stat_mode(&memory), 1);
+        assert_eq!(memory.addresses[LY], VISIBLE_LINES);
+        assert_eq!(memory.addresses[IF], 0b00001);
+        assert!(memory.ppu.take_frame_complete());
+        assert!(!memory.ppu.take_frame_complete());
+    }
+
+    #[test]
+    fn frame_is_154_lines_of_456_dots() {
+        let mut memory = lcd_on();
+        run_to(&mut memory, VISIBLE_LINES, 0);
+        memory.ppu.take_frame_complete();
+        let mut dots = 0;
+        while !memory.ppu.take_frame_complete() {
+            step_dot(&mut memory);
+            dots += 1;
+        }
+        assert_eq!(dots, LINES_PER_FRAME as u32 * DOTS_PER_LINE as u32);
+    }
+
+    #[test]
+    fn ly_reads_0_for_most_of_line_153() {
+        let mut memory = lcd_on();
+        run_to(&mut memory, LINES_PER_FRAME - 1, 0);
+        assert_eq!(memory.addresses[LY], 153);
+        run_to(&mut memory, LINES_PER_FRAME - 1, LAST_LINE_LY_DOTS);
+        assert_eq!(memory.addresses[LY], 0);
+        assert_eq!(memory.ppu.mode, Mode::VBlank);
+        run_to(&mut memory, 0, 0);
+        assert_eq!(memory.addresses[LY], 0);
+        assert_eq!(memory.ppu.mode, Mode::OamScan);
+    }
+
+    fn take_stat_interrupt(memory: &mut Memory) -> bool {
+        let requested = memory.addresses[IF] & 0b00010 > 0;
+        memory.addresses[IF] = 0;
+        requested
+    }
+
+    #[test]
+    fn coincidence_flag_follows_lyc() {
+        let mut memory = lcd_on();
+        write_lyc(&mut memory, 1);
+        assert_eq!(memory.addresses[STAT] & STAT_COINCIDENCE, 0);
+        run_to(&mut memory, 1, 0);
+        assert_eq!(memory.addresses[STAT] & STAT_COINCIDENCE, STAT_COINCIDENCE);
+        run_to(&mut memory, 2, 0);
+        assert_eq!(memory.addresses[STAT] & STAT_COINCIDENCE, 0);
+        // changing LYC updates the flag right away
+        write_lyc(&mut memory, 2);
+        assert_eq!(memory.addresses[STAT] & STAT_COINCIDENCE, STAT_COINCIDENCE);
+    }
+
+    #[test]
+    fn lyc_153_and_0_on_the_last_line() {
+        let mut memory = lcd_on();
+        memory.model = Model::Cgb;
+        write_lyc(&mut memory, 153);
+        write_stat(&mut memory, STAT_LYC_ENABLE);
+        run_to(&mut memory, LINES_PER_FRAME - 2, 0);
+        take_stat_interrupt(&mut memory);
+        run_to(&mut memory, LINES_PER_FRAME - 1, 0);
+        assert!(take_stat_interrupt(&mut memory));
+        // LY turns 0 early, so LYC 153 only matches for a few dots
+        run_to(&mut memory, LINES_PER_FRAME - 1, LAST_LINE_LY_DOTS);
+        assert_eq!(memory.addresses[STAT] & STAT_COINCIDENCE, 0);
+        write_lyc(&mut memory, 0);
+        assert!(take_stat_interrupt(&mut memory));
+        // and LYC 0 stays matched into line 0 without a second interrupt
+        run_to(&mut memory, 0, 100);
+        assert!(!take_stat_interrupt(&mut memory));
+    }
+
+    #[test]
+    fn stat_interrupt_only_fires_on_a_rising_edge() {
+        let mut memory = lcd_on();
+        memory.model = Model::Cgb;
+        write_lyc(&mut memory, 0);
+        write_stat(&mut memory, STAT_LYC_ENABLE | STAT_HBLANK_ENABLE);
+        assert!(take_stat_interrupt(&mut memory));
+        // the line is still high from the coincidence when HBlank starts
+        run_to(&mut memory, 0, 300);
+        assert!(!take_stat_interrupt(&mut memory));
+        // it drops during the OAM scan of line 1, so the next HBlank fires again
+        run_to(&mut memory, 1, 300);
+        assert!(take_stat_interrupt(&mut memory));
+    }
+
+    #[test]
+    fn oam_source_fires_when_vblank_starts() {
+        let mut memory = lcd_on();
+        memory.model = Model::Cgb;
+        write_stat(&mut memory, STAT_OAM_ENABLE);
+        run_to(&mut memory, VISIBLE_LINES - 1, 300);
+        take_stat_interrupt(&mut memory);
+        run_to(&mut memory, VISIBLE_LINES, 0);
+        assert!(take_stat_interrupt(&mut memory));
+    }
+
+    #[test]
+    fn dmg_stat_write_fires_outside_of_mode_3() {
+        let mut memory = lcd_on();
+        write_lyc(&mut memory, 10);
+        run_to(&mut memory, 0, 100);
+        write_stat(&mut memory, 0);
+        assert!(!take_stat_interrupt(&mut memory));
+        run_to(&mut memory, 0, 300);
+        write_stat(&mut memory, 0);
+        assert!(take_stat_interrupt(&mut memory));
+        // during mode 3 a matching LYC is enough
+        run_to(&mut memory, 10, 100);
+        take_stat_interrupt(&mut memory);
+        write_stat(&mut memory, 0);
+        assert!(take_stat_interrupt(&mut memory));
+    }
+
+    #[test]
+    fn cgb_stat_write_has_no_side_effect() {
+        let mut memory = lcd_on();
+        memory.model = Model::Cgb;
+        write_lyc(&mut memory, 10);
+        run_to(&mut memory, 0, 300);
+        write_stat(&mut memory, 0);
+        assert!(!take_stat_interrupt(&mut memory));
+        assert_eq!(memory.addresses[STAT], 0x80);
+    }
+
+    #[test]
+    fn disabled_background_is_shade_0() {
+        for renderer in Renderer::ALL {
+            for lcdc in [LCDC_ENABLE, LCDC_ENABLE | LCDC_BG_ENABLE] {
+                let mut memory = Memory::default();
+                memory.ppu.set_renderer(renderer);
+                // tile 0 is solid color 3 and BGP maps every color to the darkest shade
+                memory.addresses[0x9000..0x9010].fill(0xFF);
+                memory.addresses[BGP] = 0xFF;
+                write_lcdc(&mut memory, lcdc);
+                run_to(&mut memory, 1, 0);
+                let expected = if lcdc & LCDC_BG_ENABLE > 0 { 3 } else { 0 };
+                let shades: Vec<u8> = (0..160).map(|x| memory.ppu.framebuffer.get_pixel(x, 0).shade).collect();
+                assert_eq!(shades, vec![expected; 160], "{} {:02X}", renderer.name(), lcdc);
+            }
+        }
+    }
+
+    // Background and window tiles with a different pattern in every row, a few objects
+    // (one of them tall, one behind the background) and scrolling
+    fn make_scene(wx: u8, scx: u8) -> Memory {
+        let mut memory = Memory::default();
+        for (i, byte) in memory.addresses[0x8000..0x8800].iter_mut().enumerate() {
+            *byte = (i * 37 % 251) as u8;
+        }
+        for (i, byte) in memory.addresses[0x9800..0xA000].iter_mut().enumerate() {
+            *byte = (i * 7 % 128) as u8;
+        }
+        let objects: [[u8; 4]; 5] = [[40, 30, 3, 0x00], [44, 34, 5, 0x20], [90, 100, 9, 0x80], [100, 8, 12, 0x50], [130, 160, 7, 0x00]];
+        for (i, object) in objects.iter().enumerate() {
+            memory.addresses[0xFE00 + i * 4..0xFE04 + i * 4].copy_from_slice(object);
+        }
+        memory.addresses[BGP] = 0xE4;
+        memory.addresses[0xFF48] = 0xD2;
+        memory.addresses[0xFF49] = 0x1B;
+        memory.addresses[SCX] = scx;
+        memory.addresses[SCY] = 21;
+        memory.addresses[WX] = wx;
+        memory.addresses[WY] = 60;
+        write_lcdc(&mut memory, 0xF7);
+        memory
+    }
+
+    fn render_frame(mut memory: Memory, renderer: Renderer) -> Vec<(u8, u8)> {
+        memory.ppu.set_renderer(renderer);
+        // the first frame after switching the LCD on is blank
+        for _ in 0..2 {
+            while !memory.ppu.take_frame_complete() {
+                step_dot(&mut memory);
+            }
+        }
+        memory.ppu.framebuffer.get_pixels().iter().map(|pixel| (pixel.shade, pixel.color_index)).collect()
+    }
+
+    #[test]
+    fn renderers_draw_the_same_static_scene() {
+        for (wx, scx) in [(87, 13), (0, 5), (3, 2), (166, 7)] {
+            let memory = make_scene(wx, scx);
+            let scanline = render_frame(memory.clone(), Renderer::Scanline);
+            let fifo = render_frame(memory, Renderer::Fifo);
+            let first_difference = scanline.iter().zip(&fifo).position(|(a, b)| a != b);
+            assert_eq!(first_difference, None, "WX {} SCX {}", wx, scx);
+            assert!(scanline.iter().any(|(_, color)| *color != 0));
+        }
+    }
+
+    #[test]
+    fn switching_the_lcd_off_resets_ly_and_stat() {
+        let mut memory = lcd_on();
+        run_to(&mut memory, 50, 100);
+        memory.ppu.framebuffer.set_pixel(0, 0, Pixel { shade: 3, color_index: 3 });
+        memory.ppu.take_frame_complete();
+        write_lcdc(&mut memory, 0);
+        assert_eq!(memory.addresses[LY], 0);
+        assert_eq!(stat_mode(&memory), 0);
+        assert_eq!(memory.ppu.framebuffer.get_pixel(0, 0).shade, 0);
+        // the frontend gets a white frame right away
+        assert!(memory.ppu.take_frame_complete());
+        // LY stays at 0 and cannot be written
+        for _ in 0..DOTS_PER_LINE * 3 {
+            step_dot(&mut memory);
+        }
+        utils::write_byte_to_memory(&mut memory, LY, 0x42);
+        assert_eq!(memory.addresses[LY], 0);
+        assert_eq!(stat_mode(&memory), 0);
+    }
+
+    #[test]
+    fn frames_keep_completing_while_the_lcd_is_off() {
+        let mut memory = Memory::default();
+        let mut dots = 0;
+        while !memory.ppu.take_frame_complete() {
+            step_dot(&mut memory);
+            dots += 1;
+        }
+        assert_eq!(dots, LINES_PER_FRAME as u32 * DOTS_PER_LINE as u32);
+        assert_eq!(memory.addresses[IF], 0);
+    }
+
+    #[test]
+    fn first_frame_after_switching_on_is_blank() {
+        let mut memory = Memory::default();
+        memory.addresses[0x9000..0x9010].fill(0xFF);
+        memory.addresses[BGP] = 0xFF;
+        write_lcdc(&mut memory, LCDC_ENABLE | LCDC_BG_ENABLE);
+        assert_eq!(memory.ppu.mode, Mode::OamScan);
+        assert_eq!(memory.addresses[LY], 0);
+        let frame_shades = |memory: &mut Memory| {
+            while !memory.ppu.take_frame_complete() {
+                step_dot(memory);
+            }
+            memory.ppu.framebuffer.get_pixels().iter().map(|pixel| pixel.shade).max()
+        };
+        assert_eq!(frame_shades(&mut memory), Some(0));
+        assert_eq!(frame_shades(&mut memory), Some(3));
+    }
+
+    // what the CPU sees when it writes a marker to VRAM and OAM and reads it back
+    fn cpu_round_trip(memory: &mut Memory) -> (u8, u8) {
+        memory.addresses[0x8000] = 0x11;
+        memory.addresses[0xFE00] = 0x22;
+        utils::write_byte_to_memory(memory, 0x8000, 0x33);
+        utils::write_byte_to_memory(memory, 0xFE00, 0x44);
+        (utils::read_byte_from_memory(memory, 0x8000), utils::read_byte_from_memory(memory, 0xFE00))
+    }
+
+    #[test]
+    fn vram_and_oam_locks_follow_the_mode() {
+        let mut memory = lcd_on();
+        run_to(&mut memory, 0, 40);
+        assert_eq!(memory.ppu.mode, Mode::OamScan);
+        assert_eq!(cpu_round_trip(&mut memory), (0x33, 0xFF));
+        assert_eq!(memory.addresses[0xFE00], 0x22);
+        run_to(&mut memory, 0, 100);
+        assert_eq!(memory.ppu.mode, Mode::Drawing);
+        assert_eq!(cpu_round_trip(&mut memory), (0xFF, 0xFF));
+        assert_eq!((memory.addresses[0x8000], memory.addresses[0xFE00]), (0x11, 0x22));
+        run_to(&mut memory, 0, 300);
+        assert_eq!(memory.ppu.mode, Mode::HBlank);
+        assert_eq!(cpu_round_trip(&mut memory), (0x33, 0x44));
+        run_to(&mut memory, VISIBLE_LINES, 100);
+        assert_eq!(cpu_round_trip(&mut memory), (0x33, 0x44));
+    }
+
+    #[test]
+    fn locks_are_off_with_the_lcd_or_the_setting() {
+        let mut memory = Memory::default();
+        assert_eq!(cpu_round_trip(&mut memory), (0x33, 0x44));
+        let mut memory = lcd_on();
+        memory.ppu.set_access_locks(false);
+        run_to(&mut memory, 0, 100);
+        assert_eq!(cpu_round_trip(&mut memory), (0x33, 0x44));
+    }
+}
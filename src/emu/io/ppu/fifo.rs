@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+
+use crate::emu::framebuffer::{Pixel, SCREEN_WIDTH};
+use crate::emu::io::ppu::{self, LCDC, LCDC_BG_ENABLE, LCDC_BG_TILE_MAP, LCDC_OBJECT_ENABLE, LCDC_WINDOW_TILE_MAP, LY, SCX, SCY, WX, object};
+use crate::emu::memory::Memory;
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+// dots of the first tile fetch of every line, which is thrown away
+const STARTUP_DOTS: u8 = 6;
+const OBJECT_FETCH_DOTS: u8 = 6;
+const TILE_WIDTH: usize = 8;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum FetcherStep {
+    #[default]
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+impl FetcherStep {
+    const ALL: [FetcherStep; 4] = [FetcherStep::Tile, FetcherStep::DataLow, FetcherStep::DataHigh, FetcherStep::Push];
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ObjectPixel {
+    color: u8,
+    palette_address: usize,
+    bg_priority: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Fifo {
+    bg_pixels: VecDeque<u8>,
+    // lines up with the front of `bg_pixels`
+    object_pixels: VecDeque<ObjectPixel>,
+    step: FetcherStep,
+    // dots spent in the current fetcher step, every step but the push takes 2
+    step_dots: u8,
+    // tile column the fetcher works on, relative to the start of the background or window
+    tile_x: u8,
+    tile_id: u8,
+    low: u8,
+    high: u8,
+    fetching_window: bool,
+    // screen x of the next pixel that gets shifted out
+    x: u8,
+    // pixels dropped at the start of the line for the SCX fine scroll, or for a window left of the screen
+    discard: u8,
+    startup: u8,
+    // index into the objects of the OAM scan of the next object to fetch, they are sorted by X
+    next_object: usize,
+    // remaining dots of an object fetch, shifting pixels out is paused until it is done
+    object_fetch: Option<u8>,
+    window_drawn: bool,
+    // WX 166 on the previous line makes the window cover this whole line
+    window_wraps: bool,
+}
+
+impl Fifo {
+    pub fn has_drawn_window(&self) -> bool {
+        self.window_drawn
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bg_pixels.len() as u8);
+        for color in &self.bg_pixels {
+            writer.write_u8(*color);
+        }
+        writer.write_u8(self.object_pixels.len() as u8);
+        for pixel in &self.object_pixels {
+            writer.write_u8(pixel.color);
+            writer.write_u16(pixel.palette_address as u16);
+            writer.write_bool(pixel.bg_priority);
+        }
+        let step = FetcherStep::ALL.iter().position(|step| *step == self.step).unwrap_or_default();
+        for value in [step as u8, self.step_dots, self.tile_x, self.tile_id, self.low, self.high] {
+            writer.write_u8(value);
+        }
+        writer.write_bool(self.fetching_window);
+        for value in [self.x, self.discard, self.startup, self.next_object as u8] {
+            writer.write_u8(value);
+        }
+        writer.write_bool(self.object_fetch.is_some());
+        writer.write_u8(self.object_fetch.unwrap_or_default());
+        writer.write_bool(self.window_drawn);
+        writer.write_bool(self.window_wraps);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let len = reader.read_u8()?;
+        self.bg_pixels = (0..len).map(|_| reader.read_u8()).collect::<Result<_, _>>()?;
+        let len = reader.read_u8()?;
+        self.object_pixels = (0..len)
+            .map(|_| {
+                Ok(ObjectPixel {
+                    color: reader.read_u8()?,
+                    palette_address: reader.read_u16()? as usize,
+                    bg_priority: reader.read_bool()?,
+                })
+            })
+            .collect::<Result<_, StateError>>()?;
+        self.step = *FetcherStep::ALL.get(reader.read_u8()? as usize).ok_or(StateError::Invalid)?;
+        self.step_dots = reader.read_u8()?;
+        self.tile_x = reader.read_u8()?;
+        self.tile_id = reader.read_u8()?;
+        self.low = reader.read_u8()?;
+        self.high = reader.read_u8()?;
+        self.fetching_window = reader.read_bool()?;
+        self.x = reader.read_u8()?;
+        self.discard = reader.read_u8()?;
+        self.startup = reader.read_u8()?;
+        self.next_object = reader.read_u8()? as usize;
+        let has_object_fetch = reader.read_bool()?;
+        let object_fetch = reader.read_u8()?;
+        self.object_fetch = has_object_fetch.then_some(object_fetch);
+        self.window_drawn = reader.read_bool()?;
+        self.window_wraps = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+// Resets the FIFOs and the fetcher at the start of mode 3
+pub fn start_line(memory: &mut Memory) {
+    let window_wraps = ppu::is_window_enabled(memory, memory.addresses[LCDC]) && std::mem::replace(&mut memory.ppu.window_wraps, memory.addresses[WX] == 166);
+    memory.ppu.fifo = Fifo {
+        discard: memory.addresses[SCX] % 8,
+        startup: STARTUP_DOTS,
+        window_wraps,
+        ..Default::default()
+    };
+}
+
+// Advances mode 3 by one dot, returns true once the last pixel of the line has been shifted out
+pub fn step(memory: &mut Memory) -> bool {
+    let mut fifo = std::mem::take(&mut memory.ppu.fifo);
+    let done = step_dot(memory, &mut fifo);
+    memory.ppu.fifo = fifo;
+    done
+}
+
+fn step_dot(memory: &mut Memory, fifo: &mut Fifo) -> bool {
+    if fifo.x as usize >= SCREEN_WIDTH {
+        return true;
+    }
+    if fifo.startup > 0 {
+        fifo.startup -= 1;
+        return false;
+    }
+    if !fifo.fetching_window && fifo.discard == 0 && should_start_window(memory, fifo) {
+        start_window(memory, fifo);
+    }
+    step_fetcher(memory, fifo);
+    if let Some(remaining) = fifo.object_fetch {
+        if remaining > 1 {
+            fifo.object_fetch = Some(remaining - 1);
+            return false;
+        }
+        fifo.object_fetch = None;
+        fetch_object(memory, fifo);
+    }
+    if fifo.bg_pixels.is_empty() {
+        return false;
+    }
+    if fifo.discard == 0 && start_object_fetch(memory, fifo) {
+        return false;
+    }
+    let bg_color = fifo.bg_pixels.pop_front().unwrap_or(0);
+    let object_pixel = fifo.object_pixels.pop_front();
+    if fifo.discard > 0 {
+        fifo.discard -= 1;
+        return false;
+    }
+    // the registers are read as the pixel is shifted out, so mid line writes take effect right away
+    let lcdc = memory.addresses[LCDC];
+    let bg_color = if lcdc & LCDC_BG_ENABLE > 0 { bg_color } else { 0 };
+    let (color_index, shade) = match object_pixel {
+        // objects behind the background only show through background color 0
+        Some(pixel) if pixel.color != 0 && lcdc & LCDC_OBJECT_ENABLE > 0 && (!pixel.bg_priority || bg_color == 0) => {
+            (pixel.color, ppu::apply_palette(memory.addresses[pixel.palette_address], pixel.color))
+        }
+        _ => (bg_color, ppu::get_bg_shade(memory, lcdc, bg_color)),
+    };
+    let pixel = Pixel { shade, color_index };
+    memory.ppu.framebuffer.set_pixel(fifo.x as usize, memory.ppu.line as usize, pixel);
+    fifo.x += 1;
+    fifo.x as usize >= SCREEN_WIDTH
+}
+
+fn should_start_window(memory: &Memory, fifo: &Fifo) -> bool {
+    if !ppu::is_window_enabled(memory, memory.addresses[LCDC]) {
+        return false;
+    }
+    match memory.addresses[WX] {
+        _ if fifo.window_wraps => fifo.x == 0,
+        0..7 => fifo.x == 0,
+        wx => fifo.x as u16 + 7 == wx as u16,
+    }
+}
+
+// Throws away the background pixels still queued and restarts the fetcher on the window
+fn start_window(memory: &Memory, fifo: &mut Fifo) {
+    fifo.bg_pixels.clear();
+    fifo.fetching_window = true;
+    fifo.window_drawn = true;
+    fifo.tile_x = 0;
+    fifo.step = FetcherStep::Tile;
+    fifo.step_dots = 0;
+    // a window left of the screen edge starts with its first columns cut off,
+    // at WX 0 the fine scroll of the background delays it
+    fifo.discard = match memory.addresses[WX] {
+        _ if fifo.window_wraps => 0,
+        0 => 7 - memory.addresses[SCX] % 8,
+        wx => 7u8.saturating_sub(wx),
+    };
+}
+
+fn step_fetcher(memory: &Memory, fifo: &mut Fifo) {
+    if fifo.step == FetcherStep::Push {
+        if fifo.bg_pixels.is_empty() {
+            push_tile_row(fifo);
+        }
+        return;
+    }
+    fifo.step_dots += 1;
+    if fifo.step_dots < 2 {
+        return;
+    }
+    fifo.step_dots = 0;
+    let lcdc = memory.addresses[LCDC];
+    let y = get_fetcher_y(memory, fifo);
+    fifo.step = match fifo.step {
+        FetcherStep::Tile => {
+            fifo.tile_id = memory.addresses[get_tile_map_address(memory, fifo)];
+            FetcherStep::DataLow
+        }
+        FetcherStep::DataLow => {
+            fifo.low = memory.addresses[ppu::get_tile_address(lcdc, fifo.tile_id) + (y as usize % 8) * 2];
+            FetcherStep::DataHigh
+        }
+        _ => {
+            fifo.high = memory.addresses[ppu::get_tile_address(lcdc, fifo.tile_id) + (y as usize % 8) * 2 + 1];
+            FetcherStep::Push
+        }
+    };
+}
+
+fn get_fetcher_y(memory: &Memory, fifo: &Fifo) -> u8 {
+    if fifo.fetching_window {
+        memory.ppu.window_line
+    } else {
+        memory.addresses[LY].wrapping_add(memory.addresses[SCY])
+    }
+}
+
+// SCX and SCY are read for every tile, so changing them mid line moves the rest of it
+fn get_tile_map_address(memory: &Memory, fifo: &Fifo) -> usize {
+    let lcdc = memory.addresses[LCDC];
+    let y = get_fetcher_y(memory, fifo) as usize / 8;
+    let (tile_map, x) = if fifo.fetching_window {
+        (if lcdc & LCDC_WINDOW_TILE_MAP > 0 { 0x9C00 } else { 0x9800 }, fifo.tile_x as usize)
+    } else {
+        (if lcdc & LCDC_BG_TILE_MAP > 0 { 0x9C00 } else { 0x9800 }, (memory.addresses[SCX] as usize / 8 + fifo.tile_x as usize) % 32)
+    };
+    tile_map + y * 32 + x % 32
+}
+
+fn push_tile_row(fifo: &mut Fifo) {
+    for x in 0..TILE_WIDTH as u8 {
+        fifo.bg_pixels.push_back(ppu::get_bit_at_pos(fifo.high, 7 - x) << 1 | ppu::get_bit_at_pos(fifo.low, 7 - x));
+    }
+    fifo.tile_x = fifo.tile_x.wrapping_add(1);
+    fifo.step = FetcherStep::Tile;
+}
+
+// Pauses shifting when an object starts at the current X. The fetch waits for the background
+// fetcher to finish its tile first, which makes the penalty anywhere between 6 and 11 dots.
+fn start_object_fetch(memory: &Memory, fifo: &mut Fifo) -> bool {
+    if memory.addresses[LCDC] & LCDC_OBJECT_ENABLE == 0 {
+        return false;
+    }
+    match memory.ppu.objects.get(fifo.next_object) {
+        Some(object) if object.x <= fifo.x as i16 => {
+            let fetcher_dots = match fifo.step {
+                FetcherStep::Tile => 6 - fifo.step_dots,
+                FetcherStep::DataLow => 4 - fifo.step_dots,
+                FetcherStep::DataHigh => 2 - fifo.step_dots,
+                FetcherStep::Push => 0,
+            };
+            fifo.object_fetch = Some(OBJECT_FETCH_DOTS + fetcher_dots.min(5));
+            true
+        }
+        _ => false,
+    }
+}
+
+// Mixes the row of the fetched object into the object FIFO, pixels of earlier objects keep priority
+fn fetch_object(memory: &Memory, fifo: &mut Fifo) {
+    let Some(object) = memory.ppu.objects.get(fifo.next_object) else {
+        return;
+    };
+    fifo.next_object += 1;
+    let line = memory.ppu.line;
+    fifo.object_pixels.resize(TILE_WIDTH, ObjectPixel::default());
+    for column in 0..TILE_WIDTH {
+        let x = object.x + column as i16;
+        if x < fifo.x as i16 {
+            continue;
+        }
+        let pixel = ObjectPixel {
+            color: object::get_object_color(memory, object, x as u8, line),
+            palette_address: object.get_palette_address(),
+            bg_priority: object.has_bg_priority(),
+        };
+        let existing = &mut fifo.object_pixels[(x - fifo.x as i16) as usize];
+        if existing.color == 0 {
+            *existing = pixel;
+        }
+    }
+}
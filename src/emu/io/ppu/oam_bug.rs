@@ -0,0 +1,190 @@
+use crate::emu::io::ppu::{self, Mode, OAM_SCAN_DOTS};
+use crate::emu::memory::Memory;
+
+const OAM_START: usize = 0xFE00;
+const ROW_LEN: usize = 8;
+const ROWS: usize = 20;
+
+// On pre-CGB models, putting an address from 0xFE00-0xFEFF on the bus during OAM scan
+// corrupts the OAM row the PPU is reading at that moment. Instructions run with the bus
+// already clocked up to their last M-cycle, `cycles_left` is how many M-cycles of the
+// current instruction follow the access.
+fn get_corrupted_row(memory: &Memory, address: u16, cycles_left: u8) -> Option<usize> {
+    if memory.model.is_cgb() || !ppu::is_lcd_enabled(memory) || !matches!(memory.ppu.mode, Mode::OamScan | Mode::Drawing) || !(0xFE00..=0xFEFF).contains(&address) {
+        return None;
+    }
+    // an access on the previous line, which is past its OAM scan
+    let dot = memory.ppu.dot.checked_sub(cycles_left as u16 * 4)?;
+    if dot >= OAM_SCAN_DOTS {
+        return None;
+    }
+    // the PPU reads one row every M-cycle, the first row is never corrupted
+    match dot as usize / 4 {
+        0 => None,
+        row => Some(row),
+    }
+}
+
+fn get_word(memory: &Memory, row: usize, index: usize) -> u16 {
+    let address = OAM_START + row * ROW_LEN + index * 2;
+    u16::from_le_bytes([memory.addresses[address], memory.addresses[address + 1]])
+}
+
+fn set_word(memory: &mut Memory, row: usize, index: usize, value: u16) {
+    let address = OAM_START + row * ROW_LEN + index * 2;
+    memory.addresses[address..address + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn copy_row(memory: &mut Memory, from: usize, to: usize) {
+    let source = OAM_START + from * ROW_LEN;
+    memory.addresses.copy_within(source..source + ROW_LEN, OAM_START + to * ROW_LEN);
+}
+
+// Replaces the first word of `row` and copies the other three from the row before it
+fn corrupt_row(memory: &mut Memory, row: usize, corrupt: fn(u16, u16, u16) -> u16) {
+    let value = corrupt(get_word(memory, row, 0), get_word(memory, row - 1, 0), get_word(memory, row - 1, 2));
+    let previous = OAM_START + (row - 1) * ROW_LEN;
+    memory.addresses.copy_within(previous + 2..previous + ROW_LEN, OAM_START + row * ROW_LEN + 2);
+    set_word(memory, row, 0, value);
+}
+
+// Writes and 16-bit increments/decrements of INC rr, DEC rr, PUSH, LD [HLI],A and LD [HLD],A
+pub fn corrupt_write(memory: &mut Memory, address: u16, cycles_left: u8) {
+    if let Some(row) = get_corrupted_row(memory, address, cycles_left) {
+        corrupt_row(memory, row, |a, b, c| ((a ^ c) & (b ^ c)) ^ c);
+    }
+}
+
+pub fn corrupt_read(memory: &mut Memory, address: u16, cycles_left: u8) {
+    if let Some(row) = get_corrupted_row(memory, address, cycles_left) {
+        corrupt_row(memory, row, |a, b, c| b | (a & c));
+    }
+}
+
+// A read and an increment/decrement in the same M-cycle, as done by POP, LD A,[HLI] and LD A,[HLD]
+pub fn corrupt_read_increase(memory: &mut Memory, address: u16, cycles_left: u8) {
+    let Some(row) = get_corrupted_row(memory, address, cycles_left) else {
+        return;
+    };
+    if (4..ROWS - 1).contains(&row) {
+        let a = get_word(memory, row - 2, 0);
+        let b = get_word(memory, row - 1, 0);
+        let c = get_word(memory, row, 0);
+        let d = get_word(memory, row - 2, 2);
+        set_word(memory, row - 1, 0, (b & (a | c | d)) | (a & c & d));
+        copy_row(memory, row - 1, row);
+        copy_row(memory, row - 1, row - 2);
+    }
+    corrupt_read(memory, address, cycles_left);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::io::ppu::{LCDC, LCDC_ENABLE};
+    use crate::emu::model::Model;
+
+    const OAM_LEN: usize = ROWS * ROW_LEN;
+
+    // LCD on and the PPU at `dot` of a line's OAM scan, OAM filled with distinct bytes
+    fn get_memory(model: Model, dot: u16) -> Memory {
+        let mut memory = Memory { model, ..Default::default() };
+        memory.addresses[LCDC] = LCDC_ENABLE;
+        memory.ppu.mode = Mode::OamScan;
+        memory.ppu.dot = dot;
+        for (i, byte) in memory.addresses[OAM_START..OAM_START + OAM_LEN].iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(73) ^ 0x5A;
+        }
+        memory
+    }
+
+    fn get_oam(memory: &Memory) -> Vec<u8> {
+        memory.addresses[OAM_START..OAM_START + OAM_LEN].to_vec()
+    }
+
+    fn get_row(oam: &[u8], row: usize) -> &[u8] {
+        &oam[row * ROW_LEN..(row + 1) * ROW_LEN]
+    }
+
+    fn get_oam_word(oam: &[u8], row: usize, index: usize) -> u16 {
+        u16::from_le_bytes([oam[row * ROW_LEN + index * 2], oam[row * ROW_LEN + index * 2 + 1]])
+    }
+
+    // all rows but `rows` are unchanged
+    fn assert_other_rows_unchanged(before: &[u8], after: &[u8], rows: &[usize]) {
+        for row in (0..ROWS).filter(|row| !rows.contains(row)) {
+            assert_eq!(get_row(before, row), get_row(after, row), "row {}", row);
+        }
+    }
+
+    #[test]
+    fn write_corrupts_current_row() {
+        let mut memory = get_memory(Model::Dmg, 20);
+        let before = get_oam(&memory);
+        corrupt_write(&mut memory, 0xFE00, 0);
+        let after = get_oam(&memory);
+        let (a, b, c) = (get_oam_word(&before, 5, 0), get_oam_word(&before, 4, 0), get_oam_word(&before, 4, 2));
+        assert_eq!(get_oam_word(&after, 5, 0), ((a ^ c) & (b ^ c)) ^ c);
+        assert_eq!(get_row(&after, 5)[2..], get_row(&before, 4)[2..]);
+        assert_other_rows_unchanged(&before, &after, &[5]);
+    }
+
+    #[test]
+    fn read_corrupts_current_row() {
+        let mut memory = get_memory(Model::Dmg, 20);
+        let before = get_oam(&memory);
+        corrupt_read(&mut memory, 0xFE10, 0);
+        let after = get_oam(&memory);
+        let (a, b, c) = (get_oam_word(&before, 5, 0), get_oam_word(&before, 4, 0), get_oam_word(&before, 4, 2));
+        assert_eq!(get_oam_word(&after, 5, 0), b | (a & c));
+        assert_eq!(get_row(&after, 5)[2..], get_row(&before, 4)[2..]);
+        assert_other_rows_unchanged(&before, &after, &[5]);
+    }
+
+    #[test]
+    fn read_increase_corrupts_three_rows() {
+        let mut memory = get_memory(Model::Dmg, 32);
+        let before = get_oam(&memory);
+        corrupt_read_increase(&mut memory, 0xFEA0, 0);
+        let after = get_oam(&memory);
+        let (a, b, c, d) = (get_oam_word(&before, 6, 0), get_oam_word(&before, 7, 0), get_oam_word(&before, 8, 0), get_oam_word(&before, 6, 2));
+        let value = (b & (a | c | d)) | (a & c & d);
+        // rows 6 and 8 become copies of row 7, the read corruption afterwards keeps row 8 as it is
+        for row in 6..=8 {
+            assert_eq!(get_oam_word(&after, row, 0), value, "row {}", row);
+            assert_eq!(get_row(&after, row)[2..], get_row(&before, 7)[2..], "row {}", row);
+        }
+        assert_other_rows_unchanged(&before, &after, &[6, 7, 8]);
+    }
+
+    #[test]
+    fn first_row_and_other_phases_are_never_corrupted() {
+        for (mode, dot) in [(Mode::OamScan, 2), (Mode::Drawing, 80), (Mode::HBlank, 300), (Mode::VBlank, 20)] {
+            let mut memory = get_memory(Model::Dmg, dot);
+            memory.ppu.mode = mode;
+            let before = get_oam(&memory);
+            corrupt_write(&mut memory, 0xFE00, 0);
+            assert_eq!(get_oam(&memory), before, "{:?} at dot {}", mode, dot);
+        }
+    }
+
+    #[test]
+    fn earlier_cycles_of_instruction_hit_earlier_rows() {
+        // the access happened one M-cycle before the bus position, the PPU was reading row 6
+        let mut memory = get_memory(Model::Dmg, 28);
+        let before = get_oam(&memory);
+        corrupt_write(&mut memory, 0xFE00, 1);
+        assert_other_rows_unchanged(&before, &get_oam(&memory), &[6]);
+        assert_ne!(get_row(&before, 6), get_row(&get_oam(&memory), 6));
+    }
+
+    #[test]
+    fn only_cgb_is_exempt() {
+        for model in Model::ALL {
+            let mut memory = get_memory(model, 20);
+            let before = get_oam(&memory);
+            corrupt_write(&mut memory, 0xFE00, 0);
+            assert_eq!(get_oam(&memory) != before, model != Model::Cgb, "{}", model.name());
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::emu::io::ppu::{self, LCDC};
+use crate::emu::memory::Memory;
+
+const OAM_START: usize = 0xFE00;
+const OAM_ENTRIES: usize = 40;
+const OBJECTS_PER_LINE: usize = 10;
+const OBP0: usize = 0xFF48;
+const OBP1: usize = 0xFF49;
+
+const LCDC_OBJECT_SIZE: u8 = 0b00000100;
+
+const ATTRIBUTE_PALETTE: u8 = 0b00010000;
+const ATTRIBUTE_X_FLIP: u8 = 0b00100000;
+const ATTRIBUTE_Y_FLIP: u8 = 0b01000000;
+const ATTRIBUTE_BG_PRIORITY: u8 = 0b10000000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Object {
+    // position of the top left corner on screen, OAM stores it offset by 16/8
+    pub y: i16,
+    pub x: i16,
+    pub tile: u8,
+    pub attributes: u8,
+}
+
+impl Object {
+    pub fn has_bg_priority(&self) -> bool {
+        self.attributes & ATTRIBUTE_BG_PRIORITY > 0
+    }
+
+    pub fn get_palette_address(&self) -> usize {
+        if self.attributes & ATTRIBUTE_PALETTE > 0 { OBP1 } else { OBP0 }
+    }
+}
+
+fn get_object_height(memory: &Memory) -> i16 {
+    if memory.addresses[LCDC] & LCDC_OBJECT_SIZE > 0 { 16 } else { 8 }
+}
+
+// Selects the first 10 objects in OAM order that overlap `line`,
+// sorted by drawing priority: lower X first, then lower OAM index
+pub fn scan_oam(memory: &Memory, line: u8) -> Vec<Object> {
+    let height = get_object_height(memory);
+    let mut objects: Vec<Object> = memory.addresses[OAM_START..OAM_START + OAM_ENTRIES * 4]
+        .chunks(4)
+        .map(|entry| Object {
+            y: entry[0] as i16 - 16,
+            x: entry[1] as i16 - 8,
+            tile: entry[2],
+            attributes: entry[3],
+        })
+        .filter(|object| (object.y..object.y + height).contains(&(line as i16)))
+        .take(OBJECTS_PER_LINE)
+        .collect();
+    objects.sort_by_key(|object| object.x);
+    objects
+}
+
+// color index of `object` at screen position x/line, 0 being transparent
+pub fn get_object_color(memory: &Memory, object: &Object, x: u8, line: u8) -> u8 {
+    let height = get_object_height(memory);
+    let mut column = (x as i16 - object.x) as u8;
+    let mut row = (line as i16 - object.y) as u8;
+    if object.attributes & ATTRIBUTE_X_FLIP > 0 {
+        column = 7 - column;
+    }
+    if object.attributes & ATTRIBUTE_Y_FLIP > 0 {
+        row = height as u8 - 1 - row;
+    }
+    // in 8x16 mode the lowest tile bit is ignored and the bottom half uses the next tile
+    let tile = if height == 16 { (object.tile & 0xFE) + row / 8 } else { object.tile };
+    ppu::get_tile_color(memory, 0x8000 + tile as usize * 16, column, row % 8)
+}
+
+// The highest priority opaque object pixel at x, together with the object it belongs to
+pub fn get_object_pixel<'a>(memory: &Memory, objects: &'a [Object], x: u8, line: u8) -> Option<(u8, &'a Object)> {
+    objects.iter().filter(|object| (object.x..object.x + 8).contains(&(x as i16))).map(|object| (get_object_color(memory, object, x, line), object)).find(|(color, _)| *color != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // OAM entry `index` in screen coordinates
+    fn set_object(memory: &mut Memory, index: usize, x: i16, y: i16, tile: u8, attributes: u8) {
+        let entry = OAM_START + index * 4;
+        memory.addresses[entry..entry + 4].copy_from_slice(&[(y + 16) as u8, (x + 8) as u8, tile, attributes]);
+    }
+
+    // every row of `tile` has the given color in all 8 columns
+    fn set_solid_tile(memory: &mut Memory, tile: u8, color: u8) {
+        for row in 0..8 {
+            let address = 0x8000 + tile as usize * 16 + row * 2;
+            memory.addresses[address] = if color & 1 > 0 { 0xFF } else { 0 };
+            memory.addresses[address + 1] = if color & 2 > 0 { 0xFF } else { 0 };
+        }
+    }
+
+    #[test]
+    fn at_most_10_objects_per_line_in_oam_order() {
+        let mut memory = Memory::default();
+        // a far away object first, then 12 on line 0 from right to left
+        set_object(&mut memory, 0, 0, 100, 0, 0);
+        for i in 0..12 {
+            set_object(&mut memory, i + 1, 120 - i as i16 * 10, 0, i as u8, 0);
+        }
+        let objects = scan_oam(&memory, 0);
+        assert_eq!(objects.len(), 10);
+        // the last two in OAM lose out, even though they are leftmost
+        let tiles: Vec<u8> = objects.iter().map(|object| object.tile).collect();
+        assert_eq!(tiles, [9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+        // off screen objects count towards the limit as well
+        set_object(&mut memory, 1, -8, 0, 0, 0);
+        assert_eq!(scan_oam(&memory, 0).len(), 10);
+        assert!(scan_oam(&memory, 0).iter().all(|object| object.tile != 10));
+    }
+
+    #[test]
+    fn lower_x_then_lower_oam_index_wins() {
+        let mut memory = Memory::default();
+        set_solid_tile(&mut memory, 1, 1);
+        set_solid_tile(&mut memory, 2, 2);
+        set_solid_tile(&mut memory, 3, 3);
+        set_object(&mut memory, 0, 12, 0, 1, 0);
+        set_object(&mut memory, 1, 10, 0, 2, 0);
+        set_object(&mut memory, 2, 10, 0, 3, 0);
+        let objects = scan_oam(&memory, 0);
+        let color_at = |x| get_object_pixel(&memory, &objects, x, 0).map(|(color, _)| color);
+        assert_eq!(color_at(9), None);
+        // the object further left wins over the earlier one in OAM
+        assert_eq!(color_at(12), Some(2));
+        // the same X goes to the lower OAM index
+        assert_eq!(color_at(10), Some(2));
+        assert_eq!(color_at(19), Some(1));
+    }
+
+    #[test]
+    fn transparent_pixels_show_the_next_object() {
+        let mut memory = Memory::default();
+        set_solid_tile(&mut memory, 2, 2);
+        // tile 1 only has its leftmost column set
+        for row in 0..8 {
+            memory.addresses[0x8010 + row * 2] = 0x80;
+        }
+        set_object(&mut memory, 0, 10, 0, 1, 0);
+        set_object(&mut memory, 1, 10, 0, 2, 0);
+        let objects = scan_oam(&memory, 0);
+        assert_eq!(get_object_pixel(&memory, &objects, 10, 0).map(|(color, _)| color), Some(1));
+        assert_eq!(get_object_pixel(&memory, &objects, 11, 0).map(|(color, _)| color), Some(2));
+        // flipped, the set column moves to the right edge
+        set_object(&mut memory, 0, 10, 0, 1, ATTRIBUTE_X_FLIP);
+        let objects = scan_oam(&memory, 0);
+        assert_eq!(get_object_pixel(&memory, &objects, 17, 0).map(|(color, _)| color), Some(1));
+        assert_eq!(get_object_pixel(&memory, &objects, 10, 0).map(|(color, _)| color), Some(2));
+    }
+
+    #[test]
+    fn tall_objects_use_two_tiles() {
+        let mut memory = Memory::default();
+        set_solid_tile(&mut memory, 2, 1);
+        set_solid_tile(&mut memory, 3, 2);
+        memory.addresses[LCDC] = LCDC_OBJECT_SIZE;
+        // the lowest tile bit is ignored
+        set_object(&mut memory, 0, 0, 0, 3, 0);
+        let object = scan_oam(&memory, 15)[0];
+        assert_eq!(get_object_color(&memory, &object, 0, 0), 1);
+        assert_eq!(get_object_color(&memory, &object, 0, 7), 1);
+        assert_eq!(get_object_color(&memory, &object, 0, 8), 2);
+        assert_eq!(get_object_color(&memory, &object, 0, 15), 2);
+        assert!(scan_oam(&memory, 16).is_empty());
+        // a Y flip swaps the two halves
+        set_object(&mut memory, 0, 0, 0, 3, ATTRIBUTE_Y_FLIP);
+        let object = scan_oam(&memory, 0)[0];
+        assert_eq!(get_object_color(&memory, &object, 0, 0), 2);
+        assert_eq!(get_object_color(&memory, &object, 0, 15), 1);
+        // in 8x8 mode the same object covers 8 lines only
+        memory.addresses[LCDC] = 0;
+        assert!(scan_oam(&memory, 8).is_empty());
+    }
+
+    #[test]
+    fn palette_and_priority_attributes() {
+        let object = Object {
+            y: 0,
+            x: 0,
+            tile: 0,
+            attributes: ATTRIBUTE_PALETTE | ATTRIBUTE_BG_PRIORITY,
+        };
+        assert_eq!(object.get_palette_address(), OBP1);
+        assert!(object.has_bg_priority());
+        let object = Object { attributes: 0, ..object };
+        assert_eq!(object.get_palette_address(), OBP0);
+        assert!(!object.has_bg_priority());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::emu::framebuffer::{Pixel, SCREEN_WIDTH};
+use crate::emu::io::ppu::{self, LCDC, LCDC_BG_ENABLE, LCDC_BG_TILE_MAP, LCDC_OBJECT_ENABLE, LCDC_WINDOW_TILE_MAP, SCX, SCY, WX, object};
+use crate::emu::memory::Memory;
+
+// Renders the visible part of line `ly` into the framebuffer
+pub fn render_line(memory: &mut Memory, ly: u8) {
+    let lcdc = memory.addresses[LCDC];
+    let y = ly.wrapping_add(memory.addresses[SCY]);
+    let scx = memory.addresses[SCX];
+    let tile_map = if lcdc & LCDC_BG_TILE_MAP > 0 { 0x9C00 } else { 0x9800 };
+    let window_start = get_window_start(memory, lcdc, scx);
+    let window_map = if lcdc & LCDC_WINDOW_TILE_MAP > 0 { 0x9C00 } else { 0x9800 };
+    let window_line = memory.ppu.window_line;
+    let objects = if lcdc & LCDC_OBJECT_ENABLE > 0 { std::mem::take(&mut memory.ppu.objects) } else { Vec::new() };
+    for x in 0..SCREEN_WIDTH {
+        let bg_color = match window_start {
+            Some(window_start) if x as isize >= window_start => get_tile_map_color(memory, lcdc, window_map, (x as isize - window_start) as u8, window_line),
+            _ if lcdc & LCDC_BG_ENABLE > 0 => get_tile_map_color(memory, lcdc, tile_map, (x as u8).wrapping_add(scx), y),
+            _ => 0,
+        };
+        let (color_index, shade) = match object::get_object_pixel(memory, &objects, x as u8, ly) {
+            // objects behind the background only show through background color 0
+            Some((color, object)) if !object.has_bg_priority() || bg_color == 0 => (color, ppu::apply_palette(memory.addresses[object.get_palette_address()], color)),
+            _ => (bg_color, ppu::get_bg_shade(memory, lcdc, bg_color)),
+        };
+        let pixel = Pixel { shade, color_index };
+        memory.ppu.framebuffer.set_pixel(x, ly as usize, pixel);
+    }
+    if window_start.is_some() {
+        memory.ppu.window_line += 1;
+    }
+}
+
+// Screen x at which the window starts on this line, None if it is not drawn at all
+fn get_window_start(memory: &mut Memory, lcdc: u8, scx: u8) -> Option<isize> {
+    if !ppu::is_window_enabled(memory, lcdc) {
+        return None;
+    }
+    let wx = memory.addresses[WX];
+    // WX 166 makes the window cover the whole following line
+    let wraps = std::mem::replace(&mut memory.ppu.window_wraps, wx == 166);
+    match wx {
+        _ if wraps => Some(0),
+        // the fine scroll of the background also delays a window at WX 0
+        0 => Some((scx % 8) as isize - 7),
+        167.. => None,
+        wx => Some(wx as isize - 7),
+    }
+}
+
+// color index of the pixel at x/y of the 256x256 tile map, wrapping around at the edges
+fn get_tile_map_color(memory: &Memory, lcdc: u8, tile_map: usize, x: u8, y: u8) -> u8 {
+    let tile_id = memory.addresses[tile_map + (y as usize / 8) * 32 + x as usize / 8];
+    let tile_address = ppu::get_tile_address(lcdc, tile_id);
+    ppu::get_tile_color(memory, tile_address, x % 8, y % 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emu::io::ppu::{self, BGP, LCDC_BG_ENABLE, LCDC_ENABLE, LCDC_TILE_DATA, LCDC_WINDOW_ENABLE, LCDC_WINDOW_TILE_MAP, SCX, WX, WY};
+    use crate::emu::memory::Memory;
+
+    // Window map rows alternate between a solid color 3 tile and a solid color 1 tile,
+    // the background is color 0 everywhere
+    fn setup(wx: u8, wy: u8) -> Memory {
+        let mut memory = Memory::default();
+        memory.addresses[0x8010..0x8020].fill(0xFF);
+        for row in 0..8 {
+            memory.addresses[0x8020 + row * 2] = 0xFF;
+        }
+        for row in 0..32 {
+            memory.addresses[0x9C00 + row * 32..0x9C00 + row * 32 + 32].fill(if row % 2 == 0 { 1 } else { 2 });
+        }
+        memory.addresses[BGP] = 0xE4;
+        memory.addresses[WX] = wx;
+        memory.addresses[WY] = wy;
+        ppu::write_lcdc(&mut memory, LCDC_ENABLE | LCDC_BG_ENABLE | LCDC_TILE_DATA | LCDC_WINDOW_ENABLE | LCDC_WINDOW_TILE_MAP);
+        memory
+    }
+
+    // runs until the HBlank of `line`, when the scanline renderer has drawn it
+    fn draw_line(memory: &mut Memory, line: u8) {
+        while memory.ppu.line != line || memory.ppu.mode != ppu::Mode::HBlank {
+            ppu::step_dot(memory);
+        }
+    }
+
+    fn colors(memory: &Memory, line: u8) -> Vec<u8> {
+        (0..160).map(|x| memory.ppu.framebuffer.get_pixel(x, line as usize).color_index).collect()
+    }
+
+    #[test]
+    fn window_line_only_advances_on_drawn_lines() {
+        let mut memory = setup(7, 0);
+        for line in 0..4 {
+            draw_line(&mut memory, line);
+        }
+        assert_eq!(colors(&memory, 3), vec![3; 160]);
+        // hidden for 10 lines, WX past 166 draws nothing
+        memory.addresses[WX] = 200;
+        for line in 4..14 {
+            draw_line(&mut memory, line);
+            assert_eq!(colors(&memory, line), vec![0; 160]);
+        }
+        memory.addresses[WX] = 7;
+        // window line 4, still in the first tile row
+        draw_line(&mut memory, 14);
+        assert_eq!(colors(&memory, 14), vec![3; 160]);
+        draw_line(&mut memory, 18);
+        assert_eq!(colors(&memory, 18), vec![1; 160]);
+        assert_eq!(memory.ppu.window_line, 9);
+    }
+
+    #[test]
+    fn wx_0_is_delayed_by_the_fine_scroll() {
+        // the window tile is color 1 on the left half and color 3 on the right half
+        let mut memory = setup(0, 0);
+        for row in 0..8 {
+            memory.addresses[0x8010 + row * 2 + 1] = 0x0F;
+        }
+        draw_line(&mut memory, 0);
+        assert_eq!(colors(&memory, 0)[..6], [3, 1, 1, 1, 1, 3]);
+        memory.addresses[SCX] = 3;
+        draw_line(&mut memory, 1);
+        assert_eq!(colors(&memory, 1)[..6], [3, 3, 3, 3, 1, 1]);
+    }
+
+    #[test]
+    fn wx_166_covers_the_next_line() {
+        let mut memory = setup(200, 0);
+        draw_line(&mut memory, 0);
+        assert_eq!(colors(&memory, 0), vec![0; 160]);
+        memory.addresses[WX] = 166;
+        draw_line(&mut memory, 1);
+        let mut expected = vec![0; 160];
+        expected[159] = 3;
+        assert_eq!(colors(&memory, 1), expected);
+        memory.addresses[WX] = 200;
+        draw_line(&mut memory, 2);
+        assert_eq!(colors(&memory, 2), vec![3; 160]);
+        draw_line(&mut memory, 3);
+        assert_eq!(colors(&memory, 3), vec![0; 160]);
+        assert_eq!(memory.ppu.window_line, 2);
+    }
+
+    #[test]
+    fn mid_frame_wy_and_wx_changes() {
+        let mut memory = setup(7, 100);
+        draw_line(&mut memory, 39);
+        assert_eq!(colors(&memory, 39), vec![0; 160]);
+        // WY is compared at the start of every line
+        memory.addresses[WY] = 40;
+        draw_line(&mut memory, 40);
+        assert_eq!(colors(&memory, 40), vec![3; 160]);
+        // once triggered, moving WY does not hide the window again
+        memory.addresses[WY] = 100;
+        draw_line(&mut memory, 41);
+        assert_eq!(colors(&memory, 41), vec![3; 160]);
+        // a new WX moves the window on the very next line
+        memory.addresses[WX] = 87;
+        draw_line(&mut memory, 42);
+        let mut expected = vec![0; 160];
+        expected[80..].fill(3);
+        assert_eq!(colors(&memory, 42), expected);
+        // the trigger is reset for the next frame, where WY 100 applies
+        draw_line(&mut memory, 143);
+        draw_line(&mut memory, 99);
+        assert_eq!(colors(&memory, 99), vec![0; 160]);
+        draw_line(&mut memory, 100);
+        assert_eq!(colors(&memory, 100)[80..], vec![3; 80]);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::emu::io::interrupt::{self, Interrupt};
+use crate::emu::memory::Memory;
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+pub const DIV: usize = 0xFF04;
+pub const TIMA: usize = 0xFF05;
+pub const TMA: usize = 0xFF06;
+pub const TAC: usize = 0xFF07;
+
+#[derive(Debug, Default, Clone)]
+pub struct Timer {
+    // DIV is the upper byte of this counter, it advances every T-cycle
+    divider: u16,
+    // TIMA overflowed during the last M-cycle and still reads 0x00, TMA gets loaded this cycle
+    overflowed: bool,
+    // TMA was loaded into TIMA during the last M-cycle, TIMA writes are ignored for its duration
+    reloaded: bool,
+}
+
+impl Timer {
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.divider);
+        writer.write_bool(self.overflowed);
+        writer.write_bool(self.reloaded);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.divider = reader.read_u16()?;
+        self.overflowed = reader.read_bool()?;
+        self.reloaded = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+// divider bit that feeds the TIMA increment for each TAC clock select
+fn get_selected_bit(tac: u8) -> u16 {
+    match tac & 0b11 {
+        0b00 => 1 << 9,
+        0b01 => 1 << 3,
+        0b10 => 1 << 5,
+        _ => 1 << 7,
+    }
+}
+
+// TIMA is clocked by the falling edge of this signal
+fn get_signal(divider: u16, tac: u8) -> bool {
+    tac & 0b100 > 0 && divider & get_selected_bit(tac) > 0
+}
+
+pub fn set_divider(memory: &mut Memory, divider: u16) {
+    memory.timer.divider = divider;
+    memory.addresses[DIV] = (divider >> 8) as u8;
+}
+
+fn increment_tima(memory: &mut Memory) {
+    let (value, overflowed) = memory.addresses[TIMA].overflowing_add(1);
+    memory.addresses[TIMA] = value;
+    memory.timer.overflowed = overflowed;
+}
+
+// Advances the timer by one M-cycle
+pub fn tick(memory: &mut Memory) {
+    memory.timer.reloaded = false;
+    if memory.timer.overflowed {
+        memory.timer.overflowed = false;
+        memory.timer.reloaded = true;
+        memory.addresses[TIMA] = memory.addresses[TMA];
+        interrupt::request(memory, Interrupt::Timer);
+    }
+    let tac = memory.addresses[TAC];
+    let old_signal = get_signal(memory.timer.divider, tac);
+    set_divider(memory, memory.timer.divider.wrapping_add(4));
+    if old_signal && !get_signal(memory.timer.divider, tac) {
+        increment_tima(memory);
+    }
+}
+
+pub fn write_register(memory: &mut Memory, address: usize, value: u8) {
+    let tac = memory.addresses[TAC];
+    let old_signal = get_signal(memory.timer.divider, tac);
+    match address {
+        // resetting the divider can look like a falling edge and increment TIMA
+        DIV => set_divider(memory, 0),
+        TIMA => {
+            // writing during the overflow cycle cancels the reload and the interrupt,
+            // writing during the reload cycle is overridden by TMA
+            if !memory.timer.reloaded {
+                memory.timer.overflowed = false;
+                memory.addresses[TIMA] = value;
+            }
+        }
+        TMA => {
+            memory.addresses[TMA] = value;
+            if memory.timer.reloaded {
+                memory.addresses[TIMA] = value;
+            }
+        }
+        // only the lower 3 bits exist, the rest read back as 1
+        _ => memory.addresses[TAC] = 0xF8 | value,
+    }
+    if old_signal && !get_signal(memory.timer.divider, memory.addresses[TAC]) {
+        increment_tima(memory);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::cpu::CPU;
+    use crate::emu::instruction_mapper;
+    use crate::emu::io::interrupt::IF;
+
+    const TIMER_BIT: u8 = 0b00100;
+
+    // timer enabled and clocked by divider bit 3, TIMA increments every 4 M-cycles
+    fn get_memory(divider: u16) -> Memory {
+        let mut memory = Memory::default();
+        write_register(&mut memory, TAC, 0b101);
+        set_divider(&mut memory, divider);
+        memory
+    }
+
+    #[test]
+    fn tima_increments_on_falling_edge() {
+        let mut memory = get_memory(0);
+        for _ in 0..3 {
+            tick(&mut memory);
+        }
+        assert_eq!(memory.addresses[TIMA], 0);
+        tick(&mut memory);
+        assert_eq!(memory.addresses[TIMA], 1);
+    }
+
+    #[test]
+    fn div_write_increments_tima_when_selected_bit_is_set() {
+        let mut memory = get_memory(0b1000);
+        write_register(&mut memory, DIV, 0x12);
+        assert_eq!(memory.addresses[TIMA], 1);
+        assert_eq!(memory.addresses[DIV], 0);
+
+        let mut memory = get_memory(0b0100);
+        write_register(&mut memory, DIV, 0x12);
+        assert_eq!(memory.addresses[TIMA], 0);
+    }
+
+    #[test]
+    fn tac_write_increments_tima_when_signal_falls() {
+        let mut memory = get_memory(0b1000);
+        write_register(&mut memory, TAC, 0b000);
+        assert_eq!(memory.addresses[TIMA], 1);
+    }
+
+    #[test]
+    fn overflow_reloads_tma_one_cycle_later() {
+        let mut memory = get_memory(0b1100);
+        memory.addresses[TIMA] = 0xFF;
+        write_register(&mut memory, TMA, 0x42);
+        tick(&mut memory);
+        // TIMA reads 0 for one M-cycle before the reload
+        assert_eq!(memory.addresses[TIMA], 0x00);
+        assert_eq!(memory.addresses[IF] & TIMER_BIT, 0);
+        tick(&mut memory);
+        assert_eq!(memory.addresses[TIMA], 0x42);
+        assert_eq!(memory.addresses[IF] & TIMER_BIT, TIMER_BIT);
+    }
+
+    #[test]
+    fn tima_write_during_overflow_cancels_reload() {
+        let mut memory = get_memory(0b1100);
+        memory.addresses[TIMA] = 0xFF;
+        write_register(&mut memory, TMA, 0x42);
+        tick(&mut memory);
+        write_register(&mut memory, TIMA, 0x10);
+        tick(&mut memory);
+        assert_eq!(memory.addresses[TIMA], 0x10);
+        assert_eq!(memory.addresses[IF] & TIMER_BIT, 0);
+    }
+
+    #[test]
+    fn tima_write_during_reload_is_ignored() {
+        let mut memory = get_memory(0b1100);
+        memory.addresses[TIMA] = 0xFF;
+        write_register(&mut memory, TMA, 0x42);
+        tick(&mut memory);
+        tick(&mut memory);
+        write_register(&mut memory, TIMA, 0x10);
+        assert_eq!(memory.addresses[TIMA], 0x42);
+        write_register(&mut memory, TMA, 0x24);
+        assert_eq!(memory.addresses[TIMA], 0x24);
+    }
+
+    #[test]
+    fn cpu_write_lands_in_last_cycle_of_instruction() {
+        let mut cpu = CPU::default();
+        let mut memory = get_memory(0x1000);
+        // LDH (0x04),A takes 3 M-cycles and writes DIV in the last one
+        memory.addresses[0xC000..0xC002].copy_from_slice(&[0xE0, 0x04]);
+        cpu.pc = 0xC000;
+        assert_eq!(instruction_mapper::execute_instruction(&mut cpu, &mut memory), 3);
+        assert_eq!(memory.timer.divider, 4);
+    }
+}
@@ -0,0 +1,18 @@
+use super::cpu::CPU;
+use super::instruction_mapper;
+use super::memory::Memory;
+use super::save;
+
+// Runs the CPU until the PPU has completed a frame and returns the M-cycles that took.
+// The picture is left in `memory.ppu.get_framebuffer()` for whatever frontend is attached.
+pub fn step_frame(cpu: &mut CPU, memory: &mut Memory) -> u32 {
+    let mut cycles: u32 = 0;
+    loop {
+        let instruction_cycles = instruction_mapper::execute_instruction(cpu, memory);
+        cycles += instruction_cycles as u32;
+        if memory.ppu.take_frame_complete() {
+            save::flush_if_needed(memory);
+            return cycles;
+        }
+    }
+}
@@ -0,0 +1,257 @@
+pub mod mbc1;
+pub mod mbc3;
+pub mod mbc5;
+pub mod multicart;
+pub mod sachen;
+pub mod wisdom_tree;
+
+use super::header::CartridgeHeader;
+use super::state::{StateError, StateReader, StateWriter};
+use mbc1::Mbc1;
+use mbc3::{Mbc3, Rtc};
+use mbc5::Mbc5;
+use multicart::Multicart;
+use sachen::Sachen;
+use wisdom_tree::WisdomTree;
+
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+const LOGO_ADDRESS: usize = 0x0104;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapperKind {
+    RomOnly,
+    Mbc1,
+    Mbc1Multicart,
+    Mbc3,
+    Mbc5,
+    WisdomTree,
+    SachenMmc1,
+    SachenMmc2,
+    Multicart,
+}
+
+impl MapperKind {
+    pub const ALL: [MapperKind; 9] = [
+        MapperKind::RomOnly,
+        MapperKind::Mbc1,
+        MapperKind::Mbc1Multicart,
+        MapperKind::Mbc3,
+        MapperKind::Mbc5,
+        MapperKind::WisdomTree,
+        MapperKind::SachenMmc1,
+        MapperKind::SachenMmc2,
+        MapperKind::Multicart,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MapperKind::RomOnly => "rom",
+            MapperKind::Mbc1 => "mbc1",
+            MapperKind::Mbc1Multicart => "mbc1m",
+            MapperKind::Mbc3 => "mbc3",
+            MapperKind::Mbc5 => "mbc5",
+            MapperKind::WisdomTree => "wisdom-tree",
+            MapperKind::SachenMmc1 => "sachen-mmc1",
+            MapperKind::SachenMmc2 => "sachen-mmc2",
+            MapperKind::Multicart => "multicart",
+        }
+    }
+
+    pub fn has_reliable_header(&self) -> bool {
+        !matches!(self, MapperKind::WisdomTree | MapperKind::SachenMmc1 | MapperKind::SachenMmc2 | MapperKind::Multicart)
+    }
+
+    pub fn from_name(name: &str) -> Option<MapperKind> {
+        MapperKind::ALL.into_iter().find(|kind| kind.name() == name.to_ascii_lowercase())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Mapper {
+    RomOnly,
+    Mbc1(Mbc1),
+    Mbc3(Mbc3),
+    Mbc5(Mbc5),
+    WisdomTree(WisdomTree),
+    Sachen(Sachen),
+    Multicart(Multicart),
+}
+
+impl Mapper {
+    pub fn new(kind: MapperKind) -> Self {
+        match kind {
+            MapperKind::RomOnly => Mapper::RomOnly,
+            MapperKind::Mbc1 => Mapper::Mbc1(Mbc1::new(false)),
+            MapperKind::Mbc1Multicart => Mapper::Mbc1(Mbc1::new(true)),
+            MapperKind::Mbc3 => Mapper::Mbc3(Mbc3::default()),
+            MapperKind::Mbc5 => Mapper::Mbc5(Mbc5::default()),
+            MapperKind::WisdomTree => Mapper::WisdomTree(WisdomTree::default()),
+            MapperKind::SachenMmc1 => Mapper::Sachen(Sachen::new(false)),
+            MapperKind::SachenMmc2 => Mapper::Sachen(Sachen::new(true)),
+            MapperKind::Multicart => Mapper::Multicart(Multicart::default()),
+        }
+    }
+
+    pub fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+        match self {
+            Mapper::RomOnly => read_rom_at(rom, address),
+            Mapper::Mbc1(mbc1) => mbc1.read_rom(rom, address),
+            Mapper::Mbc3(mbc3) => mbc3.read_rom(rom, address),
+            Mapper::Mbc5(mbc5) => mbc5.read_rom(rom, address),
+            Mapper::WisdomTree(wisdom_tree) => wisdom_tree.read_rom(rom, address),
+            Mapper::Sachen(sachen) => sachen.read_rom(rom, address),
+            Mapper::Multicart(multicart) => multicart.read_rom(rom, address),
+        }
+    }
+
+    pub fn write_rom(&mut self, address: usize, value: u8) {
+        match self {
+            Mapper::RomOnly => (),
+            Mapper::Mbc1(mbc1) => mbc1.write_rom(address, value),
+            Mapper::Mbc3(mbc3) => mbc3.write_rom(address, value),
+            Mapper::Mbc5(mbc5) => mbc5.write_rom(address, value),
+            Mapper::WisdomTree(wisdom_tree) => wisdom_tree.write_rom(address),
+            Mapper::Sachen(sachen) => sachen.write_rom(address, value),
+            Mapper::Multicart(multicart) => multicart.write_rom(value),
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+        match self {
+            Mapper::Mbc1(mbc1) => mbc1.read_ram(ram, address),
+            Mapper::Mbc3(mbc3) => mbc3.read_ram(ram, address),
+            Mapper::Mbc5(mbc5) => mbc5.read_ram(ram, address),
+            _ => read_ram_at(ram, address - 0xA000),
+        }
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], address: usize, value: u8) {
+        match self {
+            Mapper::Mbc1(mbc1) => mbc1.write_ram(ram, address, value),
+            Mapper::Mbc3(mbc3) => mbc3.write_ram(ram, address, value),
+            Mapper::Mbc5(mbc5) => mbc5.write_ram(ram, address, value),
+            _ => write_ram_at(ram, address - 0xA000, value),
+        }
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        match self {
+            Mapper::Mbc3(mbc3) => Some(&mut mbc3.rtc),
+            _ => None,
+        }
+    }
+
+    // Called for every bus access, some mappers react to the address lines alone
+    pub fn observe_address(&self, address: usize) {
+        if let Mapper::Sachen(sachen) = self {
+            sachen.observe_address(address);
+        }
+    }
+
+    fn get_state_tag(&self) -> u8 {
+        match self {
+            Mapper::RomOnly => 0,
+            Mapper::Mbc1(_) => 1,
+            Mapper::Mbc3(_) => 2,
+            Mapper::Mbc5(_) => 3,
+            Mapper::WisdomTree(_) => 4,
+            Mapper::Sachen(_) => 5,
+            Mapper::Multicart(_) => 6,
+        }
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.get_state_tag());
+        match self {
+            Mapper::RomOnly => (),
+            Mapper::Mbc1(mbc1) => mbc1.write_state(writer),
+            Mapper::Mbc3(mbc3) => mbc3.write_state(writer),
+            Mapper::Mbc5(mbc5) => mbc5.write_state(writer),
+            Mapper::WisdomTree(wisdom_tree) => wisdom_tree.write_state(writer),
+            Mapper::Sachen(sachen) => sachen.write_state(writer),
+            Mapper::Multicart(multicart) => multicart.write_state(writer),
+        }
+    }
+
+    // Only the registers are restored, the kind of mapper comes with the cartridge
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        if reader.read_u8()? != self.get_state_tag() {
+            return Err(StateError::CartridgeMismatch);
+        }
+        match self {
+            Mapper::RomOnly => Ok(()),
+            Mapper::Mbc1(mbc1) => mbc1.read_state(reader),
+            Mapper::Mbc3(mbc3) => mbc3.read_state(reader),
+            Mapper::Mbc5(mbc5) => mbc5.read_state(reader),
+            Mapper::WisdomTree(wisdom_tree) => wisdom_tree.read_state(reader),
+            Mapper::Sachen(sachen) => sachen.read_state(reader),
+            Mapper::Multicart(multicart) => multicart.read_state(reader),
+        }
+    }
+
+    // The HLE boot sequence never performs the bus accesses that release
+    // mappers which hide their header until the boot ROM has finished
+    pub fn skip_boot_lock(&mut self) {
+        if let Mapper::Sachen(sachen) = self {
+            sachen.unlock();
+        }
+    }
+}
+
+pub fn read_rom_at(rom: &[u8], offset: usize) -> u8 {
+    if rom.is_empty() {
+        return 0xFF;
+    }
+    rom[offset % rom.len()]
+}
+
+pub fn read_rom_bank(rom: &[u8], bank: usize, address: usize) -> u8 {
+    read_rom_at(rom, bank * 0x4000 + (address & 0x3FFF))
+}
+
+pub fn read_ram_at(ram: &[u8], offset: usize) -> u8 {
+    if ram.is_empty() {
+        return 0xFF;
+    }
+    ram[offset % ram.len()]
+}
+
+pub fn write_ram_at(ram: &mut [u8], offset: usize, value: u8) {
+    if !ram.is_empty() {
+        let len = ram.len();
+        ram[offset % len] = value;
+    }
+}
+
+pub fn detect_mapper(romdata: &[u8], header: &CartridgeHeader) -> Option<MapperKind> {
+    if wisdom_tree::is_wisdom_tree(romdata, header) {
+        return Some(MapperKind::WisdomTree);
+    }
+    if sachen::is_sachen(romdata) {
+        // only the MMC2 was used on colour aware carts
+        return Some(if header.supports_cgb() { MapperKind::SachenMmc2 } else { MapperKind::SachenMmc1 });
+    }
+    if multicart::is_multicart(romdata) {
+        return Some(MapperKind::Multicart);
+    }
+    match header.cartridge_type {
+        0x00 => Some(MapperKind::RomOnly),
+        0x01..=0x03 if mbc1::is_multicart(romdata) => Some(MapperKind::Mbc1Multicart),
+        0x01..=0x03 => Some(MapperKind::Mbc1),
+        0x0F..=0x13 => Some(MapperKind::Mbc3),
+        0x19..=0x1E => Some(MapperKind::Mbc5),
+        _ => None,
+    }
+}
+
+pub fn has_logo_at(romdata: &[u8], offset: usize) -> bool {
+    romdata.get(offset..offset + NINTENDO_LOGO.len()) == Some(&NINTENDO_LOGO[..])
+}
+
+pub fn has_logo(romdata: &[u8]) -> bool {
+    has_logo_at(romdata, LOGO_ADDRESS)
+}
@@ -0,0 +1,154 @@
+use super::{has_logo_at, read_ram_at, read_rom_bank, write_ram_at};
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+#[derive(Debug, Clone)]
+pub struct Mbc1 {
+    ram_enabled: bool,
+    bank1: u8, // 5 bit ROM bank register
+    bank2: u8, // 2 bit upper ROM / RAM bank register
+    advanced_banking: bool,
+    // multicarts wire the upper register to ROM A18-A19 instead of A19-A20
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.bank1);
+        writer.write_u8(self.bank2);
+        writer.write_bool(self.advanced_banking);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ram_enabled = reader.read_bool()?;
+        self.bank1 = reader.read_u8()?;
+        self.bank2 = reader.read_u8()?;
+        self.advanced_banking = reader.read_bool()?;
+        Ok(())
+    }
+
+    pub fn new(multicart: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            advanced_banking: false,
+            multicart,
+        }
+    }
+
+    fn upper_bank(&self) -> usize {
+        let shift = if self.multicart { 4 } else { 5 };
+        (self.bank2 as usize) << shift
+    }
+
+    fn lower_bank(&self) -> usize {
+        let mask = if self.multicart { 0x0F } else { 0x1F };
+        (self.bank1 & mask) as usize
+    }
+
+    pub fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+        if address < 0x4000 {
+            let bank = if self.advanced_banking { self.upper_bank() } else { 0 };
+            return read_rom_bank(rom, bank, address);
+        }
+        read_rom_bank(rom, self.upper_bank() | self.lower_bank(), address)
+    }
+
+    pub fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // the zero check happens on the full 5 bits, even on multicarts
+                self.bank1 = value & 0x1F;
+                if self.bank1 == 0 {
+                    self.bank1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.advanced_banking = value & 0x01 > 0,
+        }
+    }
+
+    fn ram_offset(&self, address: usize) -> usize {
+        let bank = if self.advanced_banking { self.bank2 as usize } else { 0 };
+        bank * 0x2000 + (address - 0xA000)
+    }
+
+    pub fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        read_ram_at(ram, self.ram_offset(address))
+    }
+
+    pub fn write_ram(&self, ram: &mut [u8], address: usize, value: u8) {
+        if self.ram_enabled {
+            write_ram_at(ram, self.ram_offset(address), value);
+        }
+    }
+}
+
+// Multicarts are 8 Mbit images where every game (and the menu) starts on a
+// 256 KiB boundary, so the boot logo shows up again at bank 0x10
+pub fn is_multicart(romdata: &[u8]) -> bool {
+    romdata.len() == 0x100000 && has_logo_at(romdata, 0x40104)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::mapper::NINTENDO_LOGO;
+
+    fn make_rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x100000];
+        for bank in 0..0x40 {
+            rom[bank * 0x4000 + 0x2000] = bank as u8;
+        }
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x40104..0x40134].copy_from_slice(&NINTENDO_LOGO);
+        rom
+    }
+
+    fn banks(mbc1: &Mbc1, rom: &[u8]) -> (u8, u8) {
+        (mbc1.read_rom(rom, 0x2000), mbc1.read_rom(rom, 0x6000))
+    }
+
+    #[test]
+    fn detects_the_second_logo() {
+        let mut rom = make_rom();
+        assert!(is_multicart(&rom));
+        rom[0x40104] = 0;
+        assert!(!is_multicart(&rom));
+    }
+
+    #[test]
+    fn multicart_wires_upper_bits_to_a18() {
+        let rom = make_rom();
+        let mut mbc1 = Mbc1::new(true);
+        mbc1.write_rom(0x4000, 0x01);
+        mbc1.write_rom(0x2000, 0x02);
+        assert_eq!(banks(&mbc1, &rom), (0x00, 0x12));
+        // bit 4 of the lower register is not connected
+        mbc1.write_rom(0x2000, 0x12);
+        assert_eq!(banks(&mbc1, &rom), (0x00, 0x12));
+        // but still counts for the zero check, so the game header bank is reachable
+        mbc1.write_rom(0x2000, 0x10);
+        assert_eq!(banks(&mbc1, &rom), (0x00, 0x10));
+        mbc1.write_rom(0x6000, 0x01);
+        assert_eq!(banks(&mbc1, &rom), (0x10, 0x10));
+        mbc1.write_rom(0x4000, 0x03);
+        assert_eq!(banks(&mbc1, &rom), (0x30, 0x30));
+    }
+
+    #[test]
+    fn regular_wiring_uses_five_lower_bits() {
+        let rom = make_rom();
+        let mut mbc1 = Mbc1::new(false);
+        mbc1.write_rom(0x4000, 0x01);
+        mbc1.write_rom(0x2000, 0x12);
+        assert_eq!(banks(&mbc1, &rom), (0x00, 0x32));
+        mbc1.write_rom(0x2000, 0x00);
+        assert_eq!(banks(&mbc1, &rom), (0x00, 0x21));
+    }
+}
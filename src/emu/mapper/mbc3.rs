@@ -0,0 +1,169 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{read_ram_at, read_rom_bank, write_ram_at};
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+const DAY_HIGH_HALT: u8 = 0b01000000;
+const DAY_HIGH_CARRY: u8 = 0b10000000;
+
+#[derive(Debug, Clone)]
+pub struct Mbc3 {
+    ram_enabled: bool,
+    rom_bank: u8,
+    // 0x00-0x03 select a RAM bank, 0x08-0x0C an RTC register
+    ram_bank: u8,
+    latch_armed: bool,
+    pub rtc: Rtc,
+}
+
+impl Default for Mbc3 {
+    fn default() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            latch_armed: false,
+            rtc: Rtc::default(),
+        }
+    }
+}
+
+impl Mbc3 {
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_u8(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+        writer.write_bool(self.latch_armed);
+        self.rtc.write_state(writer);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u8()?;
+        self.ram_bank = reader.read_u8()?;
+        self.latch_armed = reader.read_bool()?;
+        self.rtc.read_state(reader)
+    }
+
+    pub fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+        if address < 0x4000 {
+            return read_rom_bank(rom, 0, address);
+        }
+        read_rom_bank(rom, self.rom_bank as usize, address)
+    }
+
+    pub fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = if value & 0x7F == 0 { 1 } else { value & 0x7F },
+            0x4000..=0x5FFF => self.ram_bank = value,
+            _ => {
+                // writing 0x00 then 0x01 copies the running clock into the latched registers
+                if self.latch_armed && value == 0x01 {
+                    self.rtc.latch();
+                }
+                self.latch_armed = value == 0x00;
+            }
+        }
+    }
+
+    fn ram_offset(&self, address: usize) -> usize {
+        (self.ram_bank & 0x03) as usize * 0x2000 + (address - 0xA000)
+    }
+
+    pub fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match self.ram_bank {
+            0x00..=0x03 => read_ram_at(ram, self.ram_offset(address)),
+            0x08..=0x0C => self.rtc.latched[(self.ram_bank - 0x08) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], address: usize, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        match self.ram_bank {
+            0x00..=0x03 => write_ram_at(ram, self.ram_offset(address), value),
+            0x08..=0x0C => self.rtc.write_register((self.ram_bank - 0x08) as usize, value),
+            _ => (),
+        }
+    }
+}
+
+// Registers are kept in hardware order: seconds, minutes, hours, day low, day high
+#[derive(Debug, Clone)]
+pub struct Rtc {
+    pub registers: [u8; 5],
+    pub latched: [u8; 5],
+    // wall clock time the registers were last brought up to date
+    pub last_update: u64,
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self {
+            registers: [0; 5],
+            latched: [0; 5],
+            last_update: unix_time(),
+        }
+    }
+}
+
+impl Rtc {
+    // the clock keeps the wall time of its last update, so it catches up on the time the state spent on disk
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.registers);
+        writer.write_bytes(&self.latched);
+        writer.write_u64(self.last_update);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.registers.copy_from_slice(reader.read_bytes(5)?);
+        self.latched.copy_from_slice(reader.read_bytes(5)?);
+        self.last_update = reader.read_u64()?;
+        Ok(())
+    }
+
+    pub fn latch(&mut self) {
+        self.update();
+        self.latched = self.registers;
+    }
+
+    pub fn write_register(&mut self, index: usize, value: u8) {
+        self.update();
+        self.registers[index] = value;
+        self.latched[index] = value;
+    }
+
+    pub fn update(&mut self) {
+        let now = unix_time();
+        let elapsed = now.saturating_sub(self.last_update);
+        self.last_update = now;
+        if self.registers[4] & DAY_HIGH_HALT == 0 {
+            self.advance(elapsed);
+        }
+    }
+
+    pub fn advance(&mut self, seconds: u64) {
+        let total = self.registers[0] as u64 + seconds;
+        self.registers[0] = (total % 60) as u8;
+        let total = self.registers[1] as u64 + total / 60;
+        self.registers[1] = (total % 60) as u8;
+        let total = self.registers[2] as u64 + total / 60;
+        self.registers[2] = (total % 24) as u8;
+        let day = (((self.registers[4] & 0x01) as u64) << 8 | self.registers[3] as u64) + total / 24;
+        self.registers[3] = (day & 0xFF) as u8;
+        self.registers[4] = (self.registers[4] & !0x01) | ((day >> 8) & 0x01) as u8;
+        if day > 0x1FF {
+            self.registers[4] |= DAY_HIGH_CARRY;
+        }
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
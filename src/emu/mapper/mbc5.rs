@@ -0,0 +1,68 @@
+use super::{read_ram_at, read_rom_bank, write_ram_at};
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+#[derive(Debug, Clone)]
+pub struct Mbc5 {
+    ram_enabled: bool,
+    rom_bank: u16, // 9 bit, bank 0 can be mapped to 0x4000-0x7FFF as well
+    ram_bank: u8,
+}
+
+impl Default for Mbc5 {
+    fn default() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+}
+
+impl Mbc5 {
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_enabled);
+        writer.write_u16(self.rom_bank);
+        writer.write_u8(self.ram_bank);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.ram_enabled = reader.read_bool()?;
+        self.rom_bank = reader.read_u16()?;
+        self.ram_bank = reader.read_u8()?;
+        Ok(())
+    }
+
+    pub fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+        if address < 0x4000 {
+            return read_rom_bank(rom, 0, address);
+        }
+        read_rom_bank(rom, self.rom_bank as usize, address)
+    }
+
+    pub fn write_rom(&mut self, address: usize, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => (),
+        }
+    }
+
+    fn ram_offset(&self, address: usize) -> usize {
+        self.ram_bank as usize * 0x2000 + (address - 0xA000)
+    }
+
+    pub fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        read_ram_at(ram, self.ram_offset(address))
+    }
+
+    pub fn write_ram(&self, ram: &mut [u8], address: usize, value: u8) {
+        if self.ram_enabled {
+            write_ram_at(ram, self.ram_offset(address), value);
+        }
+    }
+}
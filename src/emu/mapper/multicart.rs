@@ -0,0 +1,86 @@
+use super::{has_logo_at, read_rom_at};
+use crate::emu::header::get_header_checksum;
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+const GAME_SIZE: usize = 0x8000;
+
+// Pirate multicarts (like the M161 in Mani 4 in 1) are built from plain 32 KiB
+// games. The menu in the first slot selects a game with its first write to the
+// ROM area, after that the latch ignores every write until the next reset.
+#[derive(Debug, Default, Clone)]
+pub struct Multicart {
+    game: u8,
+    latched: bool,
+}
+
+impl Multicart {
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.game);
+        writer.write_bool(self.latched);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.game = reader.read_u8()?;
+        self.latched = reader.read_bool()?;
+        Ok(())
+    }
+
+    pub fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+        read_rom_at(rom, self.game as usize * GAME_SIZE + address)
+    }
+
+    pub fn write_rom(&mut self, value: u8) {
+        if !self.latched {
+            self.game = value;
+            self.latched = true;
+        }
+    }
+}
+
+// The header of the menu tells nothing about the mapper, but every 32 KiB
+// slot carries the complete header of its own game
+pub fn is_multicart(romdata: &[u8]) -> bool {
+    if romdata.len() <= GAME_SIZE || !romdata.len().is_multiple_of(GAME_SIZE) {
+        return false;
+    }
+    romdata.chunks(GAME_SIZE).all(|game| has_logo_at(game, 0x0104) && get_header_checksum(game) == game[0x014D])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::mapper::NINTENDO_LOGO;
+
+    fn make_rom(games: usize) -> Vec<u8> {
+        let mut rom = vec![0; games * GAME_SIZE];
+        for (i, game) in rom.chunks_mut(GAME_SIZE).enumerate() {
+            game[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+            game[0x0134] = b'A' + i as u8;
+            game[0x014D] = get_header_checksum(game);
+            game[0x4000] = i as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn detects_a_header_in_every_slot() {
+        let mut rom = make_rom(4);
+        assert!(is_multicart(&rom));
+        assert!(!is_multicart(&rom[..GAME_SIZE]));
+        rom[3 * GAME_SIZE + 0x0134] ^= 0xFF;
+        assert!(!is_multicart(&rom));
+    }
+
+    #[test]
+    fn first_write_latches_the_game() {
+        let rom = make_rom(4);
+        let mut multicart = Multicart::default();
+        assert_eq!(multicart.read_rom(&rom, 0x4000), 0);
+        multicart.write_rom(2);
+        assert_eq!(multicart.read_rom(&rom, 0x4000), 2);
+        assert_eq!(multicart.read_rom(&rom, 0x0134), b'C');
+        // games that poke MBC registers must not switch away
+        multicart.write_rom(1);
+        assert_eq!(multicart.read_rom(&rom, 0x4000), 2);
+    }
+}
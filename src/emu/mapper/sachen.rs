@@ -0,0 +1,257 @@
+use std::cell::Cell;
+
+use super::{NINTENDO_LOGO, has_logo, read_rom_bank};
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+// number of A15 rising edges before the mapper changes its lock state
+const UNLOCK_EDGES: u8 = 0x30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lock {
+    Dmg,
+    Cgb,
+    Unlocked,
+}
+
+// Sachen MMC1/MMC2 carts store their own logo at 0x0104 and hide a scrambled
+// copy of the Nintendo logo at 0x0184. Until the boot ROM is done the mapper
+// forces A7 high and swaps A0/A6 and A1/A4 for reads from 0x0100-0x01FF,
+// so the boot ROM sees a valid header. The MMC2 has a second locked stage for
+// the CGB boot ROM, where the header is only scrambled.
+#[derive(Debug, Clone)]
+pub struct Sachen {
+    base_bank: u8,
+    bank: u8,
+    mask: u8,
+    mmc2: bool,
+    lock: Cell<Lock>,
+    edges: Cell<u8>,
+    last_a15: Cell<bool>,
+}
+
+impl Sachen {
+    pub fn new(mmc2: bool) -> Self {
+        Self {
+            base_bank: 0,
+            bank: 1,
+            mask: 0,
+            mmc2,
+            lock: Cell::new(Lock::Dmg),
+            edges: Cell::new(0),
+            last_a15: Cell::new(false),
+        }
+    }
+
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        for value in [self.base_bank, self.bank, self.mask] {
+            writer.write_u8(value);
+        }
+        let lock = match self.lock.get() {
+            Lock::Dmg => 0,
+            Lock::Cgb => 1,
+            Lock::Unlocked => 2,
+        };
+        writer.write_u8(lock);
+        writer.write_u8(self.edges.get());
+        writer.write_bool(self.last_a15.get());
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.base_bank = reader.read_u8()?;
+        self.bank = reader.read_u8()?;
+        self.mask = reader.read_u8()?;
+        let lock = match reader.read_u8()? {
+            0 => Lock::Dmg,
+            1 => Lock::Cgb,
+            2 => Lock::Unlocked,
+            _ => return Err(StateError::Invalid),
+        };
+        self.lock.set(lock);
+        let edges = reader.read_u8()?;
+        if edges >= UNLOCK_EDGES {
+            return Err(StateError::Invalid);
+        }
+        self.edges.set(edges);
+        self.last_a15.set(reader.read_bool()?);
+        Ok(())
+    }
+
+    pub fn unlock(&mut self) {
+        self.lock.set(Lock::Unlocked);
+    }
+
+    pub fn observe_address(&self, address: usize) {
+        let a15 = address & 0x8000 > 0;
+        let rising_edge = a15 && !self.last_a15.get();
+        self.last_a15.set(a15);
+        if !rising_edge || self.lock.get() == Lock::Unlocked {
+            return;
+        }
+        self.edges.set(self.edges.get() + 1);
+        if self.edges.get() == UNLOCK_EDGES {
+            self.edges.set(0);
+            let next = match self.lock.get() {
+                Lock::Dmg if self.mmc2 => Lock::Cgb,
+                _ => Lock::Unlocked,
+            };
+            self.lock.set(next);
+        }
+    }
+
+    pub fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+        if address < 0x4000 {
+            let address = match self.lock.get() {
+                Lock::Unlocked => address,
+                lock => header_address(address, lock),
+            };
+            return read_rom_bank(rom, (self.base_bank & self.mask) as usize, address);
+        }
+        let bank = (self.bank & !self.mask) | (self.base_bank & self.mask);
+        read_rom_bank(rom, bank as usize, address)
+    }
+
+    pub fn write_rom(&mut self, address: usize, value: u8) {
+        // base bank and mask can only be changed while the bank register selects 0x30-0x3F
+        let registers_writable = self.bank & 0x30 == 0x30;
+        match address {
+            0x0000..=0x1FFF if registers_writable => self.base_bank = value,
+            0x2000..=0x3FFF => self.bank = if value == 0 { 1 } else { value },
+            0x4000..=0x5FFF if registers_writable => self.mask = value,
+            _ => (),
+        }
+    }
+}
+
+fn header_address(address: usize, lock: Lock) -> usize {
+    if address & 0xFF00 != 0x0100 {
+        return address;
+    }
+    let scrambled = scramble(address);
+    if lock == Lock::Dmg { scrambled | 0x80 } else { scrambled }
+}
+
+fn scramble(address: usize) -> usize {
+    let swap = |value: usize, a: usize, b: usize| {
+        let bit_a = (value >> a) & 1;
+        let bit_b = (value >> b) & 1;
+        (value & !((1 << a) | (1 << b))) | (bit_a << b) | (bit_b << a)
+    };
+    swap(swap(address, 0, 6), 1, 4)
+}
+
+pub fn is_sachen(romdata: &[u8]) -> bool {
+    if romdata.len() < 0x0200 || has_logo(romdata) {
+        return false;
+    }
+    (0..NINTENDO_LOGO.len()).all(|i| romdata[header_address(0x0104 + i, Lock::Dmg)] == NINTENDO_LOGO[i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWN_LOGO: u8 = 0x5A;
+
+    // eight banks, each tagged with its number, the own logo at 0x0104 and the
+    // Nintendo logo hidden where the locked mapper reads it from
+    fn make_rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x20000];
+        for bank in 0..8 {
+            rom[bank * 0x4000 + 0x2000] = bank as u8;
+        }
+        rom[0x0104..0x0134].fill(OWN_LOGO);
+        for (i, byte) in NINTENDO_LOGO.iter().enumerate() {
+            rom[header_address(0x0104 + i, Lock::Dmg)] = *byte;
+        }
+        rom
+    }
+
+    fn a15_edges(sachen: &Sachen, count: u8) {
+        for _ in 0..count {
+            sachen.observe_address(0x0000);
+            sachen.observe_address(0x8000);
+        }
+    }
+
+    fn logo(sachen: &Sachen, rom: &[u8]) -> Vec<u8> {
+        (0..NINTENDO_LOGO.len()).map(|i| sachen.read_rom(rom, 0x0104 + i)).collect()
+    }
+
+    #[test]
+    fn scramble_swaps_address_lines() {
+        assert_eq!(scramble(0x0101), 0x0140);
+        assert_eq!(scramble(0x0102), 0x0110);
+        assert_eq!(scramble(0x0150), 0x0103);
+        assert_eq!(header_address(0x0104, Lock::Dmg), 0x0184);
+        assert_eq!(header_address(0x0104, Lock::Cgb), 0x0104);
+        assert_eq!(header_address(0x0204, Lock::Dmg), 0x0204);
+    }
+
+    #[test]
+    fn detects_the_hidden_logo() {
+        let mut rom = make_rom();
+        assert!(is_sachen(&rom));
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        assert!(!is_sachen(&rom));
+    }
+
+    #[test]
+    fn mmc1_unlocks_after_a15_edges() {
+        let rom = make_rom();
+        let sachen = Sachen::new(false);
+        assert_eq!(logo(&sachen, &rom), NINTENDO_LOGO);
+        a15_edges(&sachen, UNLOCK_EDGES - 1);
+        assert_eq!(logo(&sachen, &rom), NINTENDO_LOGO);
+        a15_edges(&sachen, 1);
+        assert_eq!(sachen.read_rom(&rom, 0x0104), OWN_LOGO);
+    }
+
+    #[test]
+    fn mmc2_passes_through_the_cgb_stage() {
+        let rom = make_rom();
+        let sachen = Sachen::new(true);
+        a15_edges(&sachen, UNLOCK_EDGES);
+        assert_eq!(sachen.lock.get(), Lock::Cgb);
+        assert_eq!(sachen.read_rom(&rom, 0x0101), rom[0x0140]);
+        a15_edges(&sachen, UNLOCK_EDGES);
+        assert_eq!(sachen.read_rom(&rom, 0x0104), OWN_LOGO);
+    }
+
+    #[test]
+    fn base_and_mask_need_the_bank_register_unlocked() {
+        let rom = make_rom();
+        let mut sachen = Sachen::new(false);
+        sachen.unlock();
+        sachen.write_rom(0x0000, 0x04);
+        sachen.write_rom(0x4000, 0x04);
+        sachen.write_rom(0x2000, 0x01);
+        assert_eq!(sachen.read_rom(&rom, 0x6000), 1);
+
+        sachen.write_rom(0x2000, 0x30);
+        sachen.write_rom(0x0000, 0x04);
+        sachen.write_rom(0x4000, 0x04);
+        sachen.write_rom(0x2000, 0x01);
+        assert_eq!(sachen.read_rom(&rom, 0x2000), 4);
+        assert_eq!(sachen.read_rom(&rom, 0x6000), 5);
+        // bank 0 maps to 1 like on the MBC1
+        sachen.write_rom(0x2000, 0x00);
+        assert_eq!(sachen.read_rom(&rom, 0x6000), 5);
+    }
+
+    #[test]
+    fn state_keeps_the_lock_stage() {
+        let rom = make_rom();
+        let sachen = Sachen::new(true);
+        a15_edges(&sachen, UNLOCK_EDGES + 5);
+        let mut writer = StateWriter::default();
+        sachen.write_state(&mut writer);
+        let data = writer.into_bytes();
+
+        let mut restored = Sachen::new(true);
+        restored.read_state(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(restored.lock.get(), Lock::Cgb);
+        assert_eq!(restored.read_rom(&rom, 0x0101), rom[0x0140]);
+        a15_edges(&restored, UNLOCK_EDGES - 5);
+        assert_eq!(restored.read_rom(&rom, 0x0104), OWN_LOGO);
+    }
+}
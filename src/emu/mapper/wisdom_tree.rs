@@ -0,0 +1,86 @@
+use super::read_rom_at;
+use crate::emu::header::CartridgeHeader;
+use crate::emu::state::{StateError, StateReader, StateWriter};
+
+const SIGNATURES: [&[u8]; 2] = [b"WISDOM TREE", b"WISDOM\x00TREE"];
+
+// Wisdom Tree carts switch the whole 32 KiB window at once,
+// the bank number is taken from the low address byte of the write
+#[derive(Debug, Default, Clone)]
+pub struct WisdomTree {
+    bank: u8,
+}
+
+impl WisdomTree {
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bank);
+    }
+
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.bank = reader.read_u8()?;
+        Ok(())
+    }
+
+    pub fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+        read_rom_at(rom, self.bank as usize * 0x8000 + address)
+    }
+
+    pub fn write_rom(&mut self, address: usize) {
+        if address < 0x4000 {
+            self.bank = (address & 0xFF) as u8;
+        }
+    }
+}
+
+// The header usually claims a plain ROM only cart (or the unofficial 0xC0 type),
+// so the publisher string in the first bank is the most reliable hint
+pub fn is_wisdom_tree(romdata: &[u8], header: &CartridgeHeader) -> bool {
+    if header.cartridge_type == 0xC0 {
+        return true;
+    }
+    if header.cartridge_type != 0x00 || romdata.len() <= 0x8000 {
+        return false;
+    }
+    let bank_00 = &romdata[..0x4000];
+    SIGNATURES.iter().any(|signature| bank_00.windows(signature.len()).any(|window| window == *signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_rom() -> Vec<u8> {
+        let mut rom = vec![0; 4 * 0x8000];
+        for bank in 0..4 {
+            rom[bank * 0x8000 + 0x0200] = bank as u8;
+            rom[bank * 0x8000 + 0x4200] = 0x10 | bank as u8;
+        }
+        rom[0x0300..0x030B].copy_from_slice(SIGNATURES[0]);
+        rom
+    }
+
+    #[test]
+    fn detects_the_publisher_string() {
+        let mut rom = make_rom();
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(is_wisdom_tree(&rom, &header));
+        rom[0x0300] = 0;
+        assert!(!is_wisdom_tree(&rom, &header));
+        rom[0x0147] = 0xC0;
+        assert!(is_wisdom_tree(&rom, &CartridgeHeader::parse(&rom).unwrap()));
+    }
+
+    #[test]
+    fn bank_comes_from_the_address() {
+        let rom = make_rom();
+        let mut wisdom_tree = WisdomTree::default();
+        wisdom_tree.write_rom(0x0002);
+        assert_eq!(wisdom_tree.read_rom(&rom, 0x0200), 2);
+        assert_eq!(wisdom_tree.read_rom(&rom, 0x4200), 0x12);
+        wisdom_tree.write_rom(0x3F03);
+        assert_eq!(wisdom_tree.read_rom(&rom, 0x0200), 3);
+        // writes above 0x3FFF leave the bank alone
+        wisdom_tree.write_rom(0x4001);
+        assert_eq!(wisdom_tree.read_rom(&rom, 0x0200), 3);
+    }
+}
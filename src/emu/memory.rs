@@ -1,12 +1,36 @@
-#[derive(Debug)]
+use super::cartridge::Cartridge;
+use super::instructions::utils;
+use super::io::dma::{self, Dma};
+use super::io::joypad::Joypad;
+use super::io::ppu::{self, Ppu};
+use super::io::timer::{self, Timer};
+use super::model::Model;
+use super::state::{StateError, StateReader, StateWriter};
+
+#[derive(Debug, Clone)]
 pub struct Memory {
     pub addresses: Vec<u8>,
+    pub cartridge: Option<Cartridge>,
+    // mapped over 0x0000-0x00FF until 0xFF50 is written
+    pub boot_rom: Option<Vec<u8>>,
+    pub model: Model,
+    pub dma: Dma,
+    pub timer: Timer,
+    pub ppu: Ppu,
+    pub joypad: Joypad,
 }
 
 impl Default for Memory {
     fn default() -> Self {
         Self {
             addresses: initialize_memory(),
+            cartridge: None,
+            boot_rom: None,
+            model: Model::default(),
+            dma: Dma::default(),
+            timer: Timer::default(),
+            ppu: Ppu::default(),
+            joypad: Joypad::default(),
         }
     }
 }
@@ -17,22 +41,53 @@ fn initialize_memory() -> Vec<u8> {
 }
 
 impl Memory {
-    pub fn load_rom_data_into_bank_00(&mut self, romdata: &Vec<u8>) {
-        for i in 0x0000..=0x3FFF {
-            self.addresses[i] = romdata[i];
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = Some(cartridge);
+    }
+
+    // Reads without the DMA and PPU access restrictions the CPU is under and without side effects,
+    // mappers that watch the bus (like the Sachen ones) never see the access
+    pub fn read_byte(&self, address: usize) -> u8 {
+        utils::peek_byte(self, address)
+    }
+
+    // Advances every component clocked alongside the CPU by `m_cycles` machine cycles
+    pub fn tick(&mut self, m_cycles: u8) {
+        for _ in 0..m_cycles {
+            dma::tick(self);
+            timer::tick(self);
+            ppu::tick(self);
         }
     }
 
-    pub fn load_rom_data_into_bank_01(&mut self, romdata: &Vec<u8>) {
-        let cartridge_type: u8 = romdata[0x0147];
+    // Only records whether the boot ROM is still mapped, the boot ROM itself is not stored
+    pub fn write_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.addresses);
+        writer.write_bool(self.boot_rom.is_some());
+        self.dma.write_state(writer);
+        self.timer.write_state(writer);
+        self.joypad.write_state(writer);
+        self.ppu.write_state(writer);
+        if let Some(ref cartridge) = self.cartridge {
+            cartridge.write_state(writer);
+        }
+    }
 
-        // 0x00 means ROM only
-        // and currently it only supports ROM only games
-        if cartridge_type != 0x00 {
-            panic!("ROM not supported")
+    pub fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let addresses = reader.read_bytes(self.addresses.len())?;
+        self.addresses.copy_from_slice(addresses);
+        if !reader.read_bool()? {
+            self.boot_rom = None;
+        } else if self.boot_rom.is_none() {
+            return Err(StateError::BootRomMissing);
         }
-        for i in 0x4000..=0x7FFF {
-            self.addresses[i] = romdata[i];
+        self.dma.read_state(reader)?;
+        self.timer.read_state(reader)?;
+        self.joypad.read_state(reader)?;
+        self.ppu.read_state(reader)?;
+        match self.cartridge {
+            Some(ref mut cartridge) => cartridge.read_state(reader),
+            None => Ok(()),
         }
     }
 }
@@ -0,0 +1,56 @@
+use super::header::CartridgeHeader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Model {
+    Dmg0,
+    #[default]
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+}
+
+impl Model {
+    pub const ALL: [Model; 6] = [Model::Dmg0, Model::Dmg, Model::Mgb, Model::Sgb, Model::Sgb2, Model::Cgb];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::Dmg0 => "dmg0",
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::Sgb => "sgb",
+            Model::Sgb2 => "sgb2",
+            Model::Cgb => "cgb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Model> {
+        Model::ALL.into_iter().find(|model| model.name() == name.to_ascii_lowercase())
+    }
+
+    // picks the most capable hardware the cartridge asks for. CGB hardware is not emulated,
+    // so dual mode carts run in their DMG/SGB mode and only CGB-only carts get a CGB.
+    pub fn detect(header: &CartridgeHeader) -> Model {
+        if header.is_cgb_only() {
+            Model::Cgb
+        } else if header.supports_sgb() {
+            Model::Sgb
+        } else {
+            Model::Dmg
+        }
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        *self == Model::Cgb
+    }
+
+    pub fn is_sgb(&self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    // the CGB boot ROM is 2304 bytes, with a hole at 0x0100-0x01FF for the cartridge header
+    pub fn get_boot_rom_size(&self) -> usize {
+        if self.is_cgb() { 0x0900 } else { 0x0100 }
+    }
+}
@@ -0,0 +1,375 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::rom::RomError;
+
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+// source, target and patch CRC32 at the end of UPS and BPS files
+const FOOTER_LEN: usize = 12;
+// largest ROM a cartridge header can describe
+const MAX_TARGET_SIZE: usize = 0x800000;
+
+// Looks for `<rom>.ips`, `<rom>.ups` or `<rom>.bps` next to the ROM
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS.iter().map(|extension| rom_path.with_extension(extension)).find(|path| path.is_file())
+}
+
+pub fn apply_patch_file(source: Vec<u8>, patch_path: &Path) -> Result<Vec<u8>, RomError> {
+    let patch = fs::read(patch_path).map_err(|error| RomError::Unreadable(patch_path.display().to_string(), error))?;
+    apply_patch(source, &patch)
+}
+
+pub fn apply_patch(source: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, RomError> {
+    if patch.starts_with(b"PATCH") {
+        return apply_ips(source, patch);
+    }
+    if patch.starts_with(b"UPS1") {
+        return apply_ups(&source, patch);
+    }
+    if patch.starts_with(b"BPS1") {
+        return apply_bps(&source, patch);
+    }
+    Err(patch_error("unknown patch format"))
+}
+
+fn patch_error(message: &str) -> RomError {
+    RomError::Patch(message.to_string())
+}
+
+fn corrupt_error() -> RomError {
+    patch_error("patch is corrupt")
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl PatchReader<'_> {
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8], RomError> {
+        let end = self.position.checked_add(len).ok_or_else(corrupt_error)?;
+        let bytes = self.data.get(self.position..end).ok_or(patch_error("patch is truncated"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, RomError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16_be(&mut self) -> Result<usize, RomError> {
+        let bytes = self.read_bytes(2)?;
+        Ok((bytes[0] as usize) << 8 | bytes[1] as usize)
+    }
+
+    fn read_u24_be(&mut self) -> Result<usize, RomError> {
+        let bytes = self.read_bytes(3)?;
+        Ok((bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize)
+    }
+
+    // variable length integer shared by UPS and BPS, too long to fit means the patch is corrupt
+    fn read_number(&mut self) -> Result<usize, RomError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.read_byte()?;
+            value = ((byte & 0x7F) as usize).checked_mul(shift).and_then(|part| value.checked_add(part)).ok_or_else(corrupt_error)?;
+            if byte & 0x80 > 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or_else(corrupt_error)?;
+            value = value.checked_add(shift).ok_or_else(corrupt_error)?;
+        }
+    }
+
+    // target size of UPS and BPS patches
+    fn read_target_size(&mut self) -> Result<usize, RomError> {
+        let size = self.read_number()?;
+        if size > MAX_TARGET_SIZE {
+            return Err(patch_error("patched ROM would be larger than any cartridge"));
+        }
+        Ok(size)
+    }
+}
+
+fn apply_ips(mut target: Vec<u8>, patch: &[u8]) -> Result<Vec<u8>, RomError> {
+    let mut reader = PatchReader { data: patch, position: 5 };
+    loop {
+        if reader.data.get(reader.position..reader.position + 3) == Some(b"EOF") {
+            reader.position += 3;
+            break;
+        }
+        let offset = reader.read_u24_be()?;
+        let size = reader.read_u16_be()?;
+        // a zero size marks a run length encoded record
+        let (size, fill) = if size == 0 { (reader.read_u16_be()?, Some(reader.read_byte()?)) } else { (size, None) };
+        if target.len() < offset + size {
+            target.resize(offset + size, 0);
+        }
+        match fill {
+            Some(value) => target[offset..offset + size].fill(value),
+            None => target[offset..offset + size].copy_from_slice(reader.read_bytes(size)?),
+        }
+    }
+    // optional truncation extension
+    if let Ok(len) = reader.read_u24_be() {
+        target.truncate(len);
+    }
+    Ok(target)
+}
+
+fn read_footer(patch: &[u8]) -> Result<(u32, u32), RomError> {
+    if patch.len() < FOOTER_LEN + 4 {
+        return Err(patch_error("patch is truncated"));
+    }
+    let footer = &patch[patch.len() - FOOTER_LEN..];
+    let read_u32 = |index: usize| u32::from_le_bytes(footer[index * 4..index * 4 + 4].try_into().unwrap());
+    if crc32fast::hash(&patch[..patch.len() - 4]) != read_u32(2) {
+        return Err(patch_error("patch checksum mismatch, the patch file is corrupt"));
+    }
+    Ok((read_u32(0), read_u32(1)))
+}
+
+fn check_source(source: &[u8], expected_crc: u32) -> Result<(), RomError> {
+    let crc = crc32fast::hash(source);
+    if crc != expected_crc {
+        return Err(RomError::Patch(format!("source CRC32 is {:08X} but the patch expects {:08X}, it was made for a different ROM", crc, expected_crc)));
+    }
+    Ok(())
+}
+
+fn check_target(target: &[u8], expected_crc: u32) -> Result<(), RomError> {
+    let crc = crc32fast::hash(target);
+    if crc != expected_crc {
+        return Err(RomError::Patch(format!("patched CRC32 is {:08X} but the patch expects {:08X}", crc, expected_crc)));
+    }
+    Ok(())
+}
+
+fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, RomError> {
+    let (source_crc, target_crc) = read_footer(patch)?;
+    check_source(source, source_crc)?;
+    let mut reader = PatchReader { data: patch, position: 4 };
+    let _source_size = reader.read_number()?;
+    let target_size = reader.read_target_size()?;
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+    let mut position: usize = 0;
+    while reader.position < patch.len() - FOOTER_LEN {
+        position = position.checked_add(reader.read_number()?).ok_or_else(corrupt_error)?;
+        // XOR hunk until (and including) a zero byte
+        loop {
+            let value = reader.read_byte()?;
+            if position < target.len() {
+                target[position] ^= value;
+            }
+            position += 1;
+            if value == 0 {
+                break;
+            }
+        }
+    }
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, RomError> {
+    let (source_crc, target_crc) = read_footer(patch)?;
+    check_source(source, source_crc)?;
+    let mut reader = PatchReader { data: patch, position: 4 };
+    let _source_size = reader.read_number()?;
+    let target_size = reader.read_target_size()?;
+    let metadata_size = reader.read_number()?;
+    reader.read_bytes(metadata_size)?;
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+    while reader.position < patch.len() - FOOTER_LEN {
+        let data = reader.read_number()?;
+        let len = (data >> 2) + 1;
+        if target.len() + len > target_size {
+            return Err(corrupt_error());
+        }
+        match data & 0x03 {
+            // SourceRead
+            0 => {
+                let start = target.len();
+                target.extend_from_slice(start.checked_add(len).and_then(|end| source.get(start..end)).ok_or(patch_error("source read out of range"))?);
+            }
+            // TargetRead
+            1 => target.extend_from_slice(reader.read_bytes(len)?),
+            // SourceCopy
+            2 => {
+                source_offset = source_offset.checked_add(read_signed_offset(&mut reader)?).ok_or_else(corrupt_error)?;
+                let start = usize::try_from(source_offset).map_err(|_| patch_error("source copy out of range"))?;
+                target.extend_from_slice(start.checked_add(len).and_then(|end| source.get(start..end)).ok_or(patch_error("source copy out of range"))?);
+                source_offset += len as isize;
+            }
+            // TargetCopy, may overlap with the bytes it is producing
+            _ => {
+                target_offset = target_offset.checked_add(read_signed_offset(&mut reader)?).ok_or_else(corrupt_error)?;
+                for _ in 0..len {
+                    let value = *usize::try_from(target_offset).ok().and_then(|offset| target.get(offset)).ok_or(patch_error("target copy out of range"))?;
+                    target.push(value);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(patch_error("patched ROM size does not match the patch"));
+    }
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+fn read_signed_offset(reader: &mut PatchReader) -> Result<isize, RomError> {
+    let data = reader.read_number()?;
+    let value = (data >> 1) as isize;
+    Ok(if data & 1 > 0 { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_number(mut value: usize, patch: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                patch.push(0x80 | byte);
+                return;
+            }
+            patch.push(byte);
+            value -= 1;
+        }
+    }
+
+    fn push_footer(source: &[u8], target: &[u8], patch: &mut Vec<u8>) {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        let patch_crc = crc32fast::hash(patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+    }
+
+    fn make_ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = b"UPS1".to_vec();
+        encode_number(source.len(), &mut patch);
+        encode_number(target.len(), &mut patch);
+        let get_source = |i: usize| source.get(i).copied().unwrap_or(0);
+        let (mut i, mut last) = (0, 0);
+        while i < target.len() {
+            if get_source(i) == target[i] {
+                i += 1;
+                continue;
+            }
+            encode_number(i - last, &mut patch);
+            while i < target.len() && get_source(i) != target[i] {
+                patch.push(get_source(i) ^ target[i]);
+                i += 1;
+            }
+            patch.push(0);
+            i += 1;
+            last = i;
+        }
+        push_footer(source, target, &mut patch);
+        patch
+    }
+
+    fn get_rom() -> Vec<u8> {
+        (0..0x200).map(|i| (i * 7) as u8).collect()
+    }
+
+    fn is_corrupt(result: Result<Vec<u8>, RomError>) -> bool {
+        matches!(result, Err(RomError::Patch(message)) if message == "patch is corrupt")
+    }
+
+    #[test]
+    fn ips_applies_records_and_runs() {
+        let source = get_rom();
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x10, 0x00, 0x03, 0xAA, 0xBB, 0xCC]);
+        // run of 4 bytes past the end of the source, which grows the ROM
+        patch.extend_from_slice(&[0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x04, 0xEE]);
+        patch.extend_from_slice(b"EOF");
+        let target = apply_patch(source.clone(), &patch).unwrap();
+        let mut expected = source;
+        expected[0x10..0x13].copy_from_slice(&[0xAA, 0xBB, 0xCC]);
+        expected.extend_from_slice(&[0xEE; 4]);
+        assert_eq!(target, expected);
+    }
+
+    #[test]
+    fn ips_truncated_record_is_rejected() {
+        let patch = [b"PATCH".as_slice(), &[0x00, 0x00, 0x10, 0x00, 0x03, 0xAA]].concat();
+        assert!(matches!(apply_patch(get_rom(), &patch), Err(RomError::Patch(_))));
+    }
+
+    #[test]
+    fn ups_round_trip() {
+        let source = get_rom();
+        let mut target = source.clone();
+        target[0x20] = 0x00;
+        target[0x21..0x25].fill(0x55);
+        target.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(apply_patch(source.clone(), &make_ups(&source, &target)).unwrap(), target);
+    }
+
+    #[test]
+    fn ups_for_another_rom_is_rejected() {
+        let source = get_rom();
+        let patch = make_ups(&source, &[0x01; 0x200]);
+        assert!(matches!(apply_patch(vec![0; 0x200], &patch), Err(RomError::Patch(_))));
+    }
+
+    #[test]
+    fn bps_round_trip() {
+        let source = get_rom();
+        let mut target = source[..0x100].to_vec();
+        target.extend_from_slice(&[0x11, 0x22, 0x11, 0x22, 0x11, 0x22]);
+        target.extend_from_slice(&source[0x180..0x190]);
+        let mut patch = b"BPS1".to_vec();
+        encode_number(source.len(), &mut patch);
+        encode_number(target.len(), &mut patch);
+        encode_number(0, &mut patch);
+        // SourceRead
+        encode_number((0x100 - 1) << 2, &mut patch);
+        // TargetRead
+        encode_number(((2 - 1) << 2) | 1, &mut patch);
+        patch.extend_from_slice(&[0x11, 0x22]);
+        // TargetCopy overlapping the bytes it produces, repeats the 2 bytes written by the TargetRead
+        encode_number(((4 - 1) << 2) | 3, &mut patch);
+        encode_number(0x100 << 1, &mut patch);
+        // SourceCopy from 0x180
+        encode_number(((0x10 - 1) << 2) | 2, &mut patch);
+        encode_number(0x180 << 1, &mut patch);
+        push_footer(&source, &target, &mut patch);
+        assert_eq!(apply_patch(source, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn overflowing_number_is_rejected() {
+        let source = get_rom();
+        // a number that never ends, each byte asks for another 7 bits
+        let mut patch = [b"UPS1".as_slice(), &[0x00; 16]].concat();
+        push_footer(&source, &source, &mut patch);
+        assert!(is_corrupt(apply_patch(source.clone(), &patch)));
+        let mut patch = [b"BPS1".as_slice(), &[0x7F; 16]].concat();
+        push_footer(&source, &source, &mut patch);
+        assert!(is_corrupt(apply_patch(source, &patch)));
+    }
+
+    #[test]
+    fn bps_action_past_target_size_is_rejected() {
+        let source = get_rom();
+        let mut patch = b"BPS1".to_vec();
+        encode_number(source.len(), &mut patch);
+        encode_number(0x10, &mut patch);
+        encode_number(0, &mut patch);
+        // TargetCopy of a huge length
+        encode_number(usize::MAX >> 8, &mut patch);
+        push_footer(&source, &source, &mut patch);
+        assert!(is_corrupt(apply_patch(source, &patch)));
+    }
+}
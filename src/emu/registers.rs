@@ -1,4 +1,4 @@
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Registers {
     pub a: u8, // Accumulator
     f: u8,     // Flags are not addressable by themselves, only as AF
@@ -16,6 +16,10 @@ impl Registers {
         self.f = get_lower_byte(value);
     }
 
+    pub fn get_af(&self) -> u16 {
+        ((self.a as u16) << 8) | self.f as u16
+    }
+
     pub fn get_f(&self) -> u8 {
         self.f
     }
@@ -1,18 +1,133 @@
+use std::fmt;
 use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::archive;
+use super::header::CartridgeHeader;
+use super::patch;
+
+// smallest image with a full bank 00 and bank 01, anything below that cannot be a valid dump
+pub const MIN_ROM_SIZE: usize = 0x8000;
+
+#[derive(Debug)]
+pub enum RomError {
+    Missing(String),
+    Unreadable(String, io::Error),
+    TooSmall(usize),
+    SizeMismatch { header: usize, actual: usize },
+    UnsupportedMapper(u8),
+    Archive(String),
+    NoRomInArchive,
+    MissingArchiveEntry(String),
+    Patch(String),
+    BootRomSize { expected: usize, actual: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Missing(path) => write!(f, "ROM file {} does not exist", path),
+            RomError::Unreadable(path, error) => write!(f, "Could not read {}: {}", path, error),
+            RomError::TooSmall(size) => write!(f, "ROM is only {} bytes, at least {} are needed", size, MIN_ROM_SIZE),
+            RomError::SizeMismatch { header, actual } => write!(f, "ROM is {} bytes but the header says {}, the dump is probably truncated", actual, header),
+            RomError::UnsupportedMapper(cartridge_type) => write!(f, "Cartridge type {:02X} is not supported, try forcing a mapper with --mapper", cartridge_type),
+            RomError::Archive(error) => write!(f, "Could not unpack archive: {}", error),
+            RomError::NoRomInArchive => write!(f, "Archive does not contain a .gb or .gbc file"),
+            RomError::MissingArchiveEntry(name) => write!(f, "Archive has no entry named {}", name),
+            RomError::Patch(error) => write!(f, "Could not apply patch: {}", error),
+            RomError::BootRomSize { expected, actual } => write!(f, "Boot ROM is {} bytes, the selected model needs exactly {}", actual, expected),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
 
 #[derive(Debug)]
 pub struct ROM {
-    pub data: Option<Vec<u8>>,
+    pub data: Vec<u8>,
+    pub header: CartridgeHeader,
 }
 
 impl ROM {
-    pub fn load_rom_from_file(&mut self, filepath: &str) {
-        self.data = match fs::read(filepath) {
-            Ok(data) => Some(data),
-            _ => {
-                println!("Could not {}", filepath);
-                None
-            }
-        };
+    // `filepath` may point to a zip or gzip archive, optionally naming the zip entry as `archive.zip:entry.gb`.
+    // Without an explicit `patch_path` an IPS/UPS/BPS patch named after the ROM is applied if there is one,
+    // patching only ever happens in memory.
+    pub fn load_rom_from_file(filepath: &str, patch_path: Option<&str>) -> Result<ROM, RomError> {
+        let (archive_path, entry) = archive::split_entry(filepath);
+        let data = fs::read(archive_path).map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => RomError::Missing(archive_path.to_string()),
+            _ => RomError::Unreadable(archive_path.to_string(), error),
+        })?;
+        let mut data = archive::unpack(data, entry)?;
+        let patch_path = patch_path.map(|path| Path::new(path).to_path_buf()).or_else(|| patch::find_patch(Path::new(archive::strip_container_extension(filepath))));
+        if let Some(patch_path) = patch_path {
+            eprintln!("Applying patch {}", patch_path.display());
+            data = patch::apply_patch_file(data, &patch_path)?;
+        }
+        ROM::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<ROM, RomError> {
+        if data.len() < MIN_ROM_SIZE {
+            return Err(RomError::TooSmall(data.len()));
+        }
+        let header = CartridgeHeader::parse(&data).ok_or(RomError::TooSmall(data.len()))?;
+        Ok(ROM { data, header })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::cartridge::Cartridge;
+
+    fn make_rom(cartridge_type: u8, rom_size_code: u8, len: usize) -> Vec<u8> {
+        let mut data = vec![0; len];
+        data[0x0147] = cartridge_type;
+        data[0x0148] = rom_size_code;
+        data[0x014D] = crate::emu::header::get_header_checksum(&data);
+        data
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = std::env::temp_dir().join("redox-damage-does-not-exist.gb");
+        let error = ROM::load_rom_from_file(path.to_str().unwrap(), None).unwrap_err();
+        assert!(matches!(error, RomError::Missing(_)));
+    }
+
+    #[test]
+    fn too_small() {
+        let error = ROM::from_bytes(vec![0; 0x4000]).unwrap_err();
+        assert!(matches!(error, RomError::TooSmall(0x4000)));
+    }
+
+    #[test]
+    fn truncated_dump() {
+        // 64 KiB MBC1 cart cut off after 32 KiB
+        let rom = ROM::from_bytes(make_rom(0x01, 0x01, 0x8000)).unwrap();
+        let error = Cartridge::new(rom, None).unwrap_err();
+        assert!(matches!(error, RomError::SizeMismatch { header: 0x10000, actual: 0x8000 }));
+    }
+
+    #[test]
+    fn unsupported_cartridge_type() {
+        // MBC2
+        let rom = ROM::from_bytes(make_rom(0x05, 0x00, 0x8000)).unwrap();
+        let error = Cartridge::new(rom, None).unwrap_err();
+        assert!(matches!(error, RomError::UnsupportedMapper(0x05)));
+        assert!(error.to_string().contains("--mapper"));
+    }
+
+    #[test]
+    fn bad_header_checksum_only_warns() {
+        // the real boot ROM would lock up, but bootleg carts often get this wrong
+        let mut data = make_rom(0x00, 0x00, 0x8000);
+        data[0x014D] ^= 0xFF;
+        let rom = ROM::from_bytes(data).unwrap();
+        assert!(!rom.header.validation.header_checksum_valid);
+        assert!(rom.header.get_warnings().iter().any(|warning| warning.starts_with("Header checksum mismatch")));
+        assert!(Cartridge::new(rom, None).is_ok());
     }
 }
@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::archive;
+use super::cartridge::Cartridge;
+use super::mapper::mbc3::{self, Rtc};
+use super::memory::Memory;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+// VBA-M/BGB footer: 5 clock registers, 5 latched registers (u32 each) and a 64 bit timestamp,
+// older emulators wrote the timestamp as 32 bit
+const RTC_FOOTER_LEN: usize = 48;
+const RTC_FOOTER_LEN_SHORT: usize = 44;
+
+#[derive(Debug, Clone)]
+pub struct BatterySave {
+    pub path: PathBuf,
+    rtc: bool,
+    dirty: bool,
+    flush_requested: bool,
+    last_flush: Instant,
+}
+
+impl BatterySave {
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    // games disable external RAM once they are done saving, which is the safest moment to persist it
+    pub fn request_flush(&mut self) {
+        if self.dirty {
+            self.flush_requested = true;
+        }
+    }
+
+    fn needs_flush(&self) -> bool {
+        self.flush_requested || (self.dirty && self.last_flush.elapsed() >= FLUSH_INTERVAL)
+    }
+}
+
+// saves of compressed ROMs are named after the archive, so they end up next to it
+pub fn get_save_path(rom_path: &str) -> PathBuf {
+    Path::new(archive::strip_container_extension(rom_path)).with_extension("sav")
+}
+
+// Sets up persistence for battery backed carts and restores `save_path` if it exists
+pub fn attach_battery_save(cartridge: &mut Cartridge, save_path: PathBuf) {
+    if !cartridge.header.has_battery() {
+        return;
+    }
+    let mut battery_save = BatterySave {
+        path: save_path,
+        rtc: cartridge.header.has_rtc(),
+        dirty: false,
+        flush_requested: false,
+        last_flush: Instant::now(),
+    };
+    if let Ok(data) = fs::read(&battery_save.path) {
+        restore(cartridge, &data);
+    } else {
+        // make sure a fresh clock is written out even if the game never touches RAM
+        battery_save.dirty = battery_save.rtc;
+    }
+    cartridge.battery_save = Some(battery_save);
+}
+
+fn restore(cartridge: &mut Cartridge, data: &[u8]) {
+    let ram_len = cartridge.ram.len().min(data.len());
+    cartridge.ram[..ram_len].copy_from_slice(&data[..ram_len]);
+    let footer = &data[ram_len..];
+    if let Some(rtc) = cartridge.mapper.rtc_mut()
+        && (footer.len() == RTC_FOOTER_LEN || footer.len() == RTC_FOOTER_LEN_SHORT)
+    {
+        read_rtc_footer(rtc, footer);
+    }
+}
+
+fn read_rtc_footer(rtc: &mut Rtc, footer: &[u8]) {
+    let read_u32 = |index: usize| u32::from_le_bytes(footer[index * 4..index * 4 + 4].try_into().unwrap());
+    for i in 0..5 {
+        rtc.registers[i] = read_u32(i) as u8;
+        rtc.latched[i] = read_u32(i + 5) as u8;
+    }
+    let saved_at = if footer.len() == RTC_FOOTER_LEN {
+        u64::from_le_bytes(footer[40..48].try_into().unwrap())
+    } else {
+        read_u32(10) as u64
+    };
+    // catch up on the time that passed while the emulator was not running
+    rtc.last_update = saved_at;
+    rtc.update();
+}
+
+fn write_rtc_footer(rtc: &mut Rtc, data: &mut Vec<u8>) {
+    rtc.update();
+    for value in rtc.registers.iter().chain(rtc.latched.iter()) {
+        data.extend_from_slice(&(*value as u32).to_le_bytes());
+    }
+    data.extend_from_slice(&mbc3::unix_time().to_le_bytes());
+}
+
+pub fn flush_if_needed(memory: &mut Memory) {
+    let battery_save = memory.cartridge.as_ref().and_then(|cartridge| cartridge.battery_save.as_ref());
+    let needs_flush = battery_save.is_some_and(|battery_save| battery_save.needs_flush());
+    if needs_flush {
+        flush(memory);
+    }
+}
+
+pub fn flush(memory: &mut Memory) {
+    let Some(ref mut cartridge) = memory.cartridge else {
+        return;
+    };
+    let Some(mut battery_save) = cartridge.battery_save.take() else {
+        return;
+    };
+    let mut data = cartridge.ram.clone();
+    if battery_save.rtc
+        && let Some(rtc) = cartridge.mapper.rtc_mut()
+    {
+        write_rtc_footer(rtc, &mut data);
+    }
+    // write next to the save first, so a crash midway never leaves a truncated file behind
+    let temp_path = battery_save.path.with_extension("sav.tmp");
+    match fs::write(&temp_path, &data).and_then(|_| fs::rename(&temp_path, &battery_save.path)) {
+        Ok(_) => battery_save.dirty = false,
+        Err(error) => eprintln!("Could not write {}: {}", battery_save.path.display(), error),
+    }
+    battery_save.flush_requested = false;
+    battery_save.last_flush = Instant::now();
+    cartridge.battery_save = Some(battery_save);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::rom::ROM;
+
+    const HALT: u8 = 0b01000000;
+
+    // MBC3+TIMER+RAM+BATTERY with 8 KiB of RAM
+    fn make_cartridge() -> Cartridge {
+        let mut data = vec![0; 0x8000];
+        data[0x0147] = 0x10;
+        data[0x0149] = 0x02;
+        Cartridge::new(ROM::from_bytes(data).unwrap(), None).unwrap()
+    }
+
+    fn temp_save_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("redox-damage-{}-{}.sav", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn halted_rtc() -> Rtc {
+        Rtc {
+            registers: [1, 2, 3, 4, HALT],
+            latched: [5, 6, 7, 8, 9],
+            last_update: 0,
+        }
+    }
+
+    #[test]
+    fn rtc_footer_round_trip() {
+        let mut footer = Vec::new();
+        write_rtc_footer(&mut halted_rtc(), &mut footer);
+        assert_eq!(footer.len(), RTC_FOOTER_LEN);
+        let mut rtc = Rtc::default();
+        read_rtc_footer(&mut rtc, &footer);
+        assert_eq!(rtc.registers, [1, 2, 3, 4, HALT]);
+        assert_eq!(rtc.latched, [5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn short_rtc_footer_catches_up() {
+        let mut footer = Vec::new();
+        for value in [0, 0, 0, 0, 0, 5, 6, 7, 8, 9] {
+            footer.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        footer.extend_from_slice(&((mbc3::unix_time() - 90) as u32).to_le_bytes());
+        assert_eq!(footer.len(), RTC_FOOTER_LEN_SHORT);
+
+        let mut cartridge = make_cartridge();
+        let mut data = vec![0xAB; cartridge.ram.len()];
+        data.extend_from_slice(&footer);
+        restore(&mut cartridge, &data);
+        let rtc = cartridge.mapper.rtc_mut().unwrap();
+        assert_eq!(rtc.registers[1], 1);
+        assert!((30..=31).contains(&rtc.registers[0]));
+        assert_eq!(rtc.latched, [5, 6, 7, 8, 9]);
+        assert!(cartridge.ram.iter().all(|byte| *byte == 0xAB));
+    }
+
+    #[test]
+    fn restores_short_and_oversized_saves() {
+        let mut cartridge = make_cartridge();
+        restore(&mut cartridge, &[0x11; 0x100]);
+        assert!(cartridge.ram[..0x100].iter().all(|byte| *byte == 0x11));
+        assert!(cartridge.ram[0x100..].iter().all(|byte| *byte == 0));
+
+        // trailing bytes that are not a known footer are ignored
+        let mut cartridge = make_cartridge();
+        *cartridge.mapper.rtc_mut().unwrap() = halted_rtc();
+        let data = vec![0x22; cartridge.ram.len() + 100];
+        restore(&mut cartridge, &data);
+        assert!(cartridge.ram.iter().all(|byte| *byte == 0x22));
+        assert_eq!(cartridge.mapper.rtc_mut().unwrap().registers, [1, 2, 3, 4, HALT]);
+    }
+
+    #[test]
+    fn flush_writes_ram_and_clock() {
+        let path = temp_save_path("flush");
+        let mut cartridge = make_cartridge();
+        attach_battery_save(&mut cartridge, path.clone());
+        *cartridge.mapper.rtc_mut().unwrap() = halted_rtc();
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+
+        let mut memory = Memory {
+            cartridge: Some(cartridge),
+            ..Default::default()
+        };
+        // what main does on exit, no matter how long ago the last flush was
+        flush(&mut memory);
+        let data = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let ram_len = memory.cartridge.as_ref().unwrap().ram.len();
+        assert_eq!(data.len(), ram_len + RTC_FOOTER_LEN);
+        assert_eq!(data[0], 0x42);
+
+        let mut cartridge = make_cartridge();
+        restore(&mut cartridge, &data);
+        assert_eq!(cartridge.ram[0], 0x42);
+        assert_eq!(cartridge.mapper.rtc_mut().unwrap().registers, [1, 2, 3, 4, HALT]);
+        assert!(!memory.cartridge.unwrap().battery_save.unwrap().dirty);
+    }
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt;
+
+use super::model::Model;
+
+// Save states are a flat little endian byte stream in a fixed field order. Every
+// component writes and reads its own fields, a new field means a new version.
+pub const MAGIC: &[u8; 4] = b"RDSS";
+pub const VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum StateError {
+    // not a save state, or cut off
+    Invalid,
+    UnsupportedVersion(u8),
+    // the state was taken on different hardware
+    ModelMismatch { state: Model, machine: Model },
+    // the state was taken with a different game
+    CartridgeMismatch,
+    // the state was taken while the boot ROM was mapped, but this machine has none
+    BootRomMissing,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::Invalid => write!(f, "not a valid save state"),
+            StateError::UnsupportedVersion(version) => write!(f, "save state version {} is not supported, expected {}", version, VERSION),
+            StateError::ModelMismatch { state, machine } => write!(f, "state was saved on {} but this machine is {}", state.name(), machine.name()),
+            StateError::CartridgeMismatch => write!(f, "state was saved with a different cartridge"),
+            StateError::BootRomMissing => write!(f, "state was saved while the boot ROM was running, load the same boot ROM first"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // raw bytes, the reader has to know the length
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn write_model(&mut self, model: Model) {
+        let index = Model::ALL.iter().position(|candidate| *candidate == model).unwrap_or_default();
+        self.write_u8(index as u8);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self.data.get(self.position..self.position + len).ok_or(StateError::Invalid)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_model(&mut self) -> Result<Model, StateError> {
+        Model::ALL.get(self.read_u8()? as usize).copied().ok_or(StateError::Invalid)
+    }
+
+    // trailing bytes mean the state does not match the layout this version expects
+    pub fn finish(&self) -> Result<(), StateError> {
+        if self.position == self.data.len() { Ok(()) } else { Err(StateError::Invalid) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip() {
+        let mut writer = StateWriter::default();
+        writer.write_u8(0x12);
+        writer.write_bool(true);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789A_BCDE);
+        writer.write_u64(0x0102_0304_0506_0708);
+        writer.write_model(Model::Sgb2);
+        writer.write_bytes(&[1, 2, 3]);
+        let data = writer.into_bytes();
+        assert_eq!(data[2..4], [0x56, 0x34]);
+
+        let mut reader = StateReader::new(&data);
+        assert_eq!(reader.read_u8().unwrap(), 0x12);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_u16().unwrap(), 0x3456);
+        assert_eq!(reader.read_u32().unwrap(), 0x789A_BCDE);
+        assert_eq!(reader.read_u64().unwrap(), 0x0102_0304_0506_0708);
+        assert_eq!(reader.read_model().unwrap(), Model::Sgb2);
+        assert_eq!(reader.read_bytes(3).unwrap(), [1, 2, 3]);
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn rejects_short_and_malformed_data() {
+        let mut reader = StateReader::new(&[2, 0x34]);
+        assert!(matches!(reader.read_bool(), Err(StateError::Invalid)));
+        assert!(matches!(reader.read_u16(), Err(StateError::Invalid)));
+        assert!(reader.finish().is_err());
+        assert!(matches!(StateReader::new(&[0xFF]).read_model(), Err(StateError::Invalid)));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::emu::boot;
+use crate::emu::cartridge::Cartridge;
+use crate::emu::cpu::CPU;
+use crate::emu::framebuffer::Framebuffer;
+use crate::emu::instruction_mapper;
+use crate::emu::io::joypad::{self, Buttons};
+use crate::emu::io::ppu::Renderer;
+use crate::emu::machine;
+use crate::emu::memory::Memory;
+use crate::emu::model::Model;
+use crate::emu::rom::{ROM, RomError};
+use crate::emu::save;
+use crate::emu::state::{self, StateReader, StateWriter};
+
+pub use crate::emu::state::StateError;
+
+// A complete machine for embedding the emulator: CPU, bus, cartridge and peripherals
+#[derive(Debug)]
+pub struct GameBoy {
+    cpu: CPU,
+    memory: Memory,
+}
+
+// Snapshot of a running machine in the save state byte format. It holds the cartridge RAM
+// and the mapper registers, but not the ROM, so it can only be loaded with the same game.
+#[derive(Debug, Clone)]
+pub struct SaveState {
+    data: Vec<u8>,
+}
+
+impl SaveState {
+    // Checks the header of a state read back from disk, the rest is checked when it is loaded
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, StateError> {
+        read_header(&mut StateReader::new(&data))?;
+        Ok(Self { data })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn get_model(&self) -> Model {
+        let mut reader = StateReader::new(&self.data);
+        read_header(&mut reader).map(|header| header.model).unwrap_or_default()
+    }
+}
+
+struct StateHeader {
+    model: Model,
+    // global checksum of the cartridge, identifies the game without storing the ROM
+    cartridge: Option<u16>,
+}
+
+fn write_header(writer: &mut StateWriter, memory: &Memory) {
+    writer.write_bytes(state::MAGIC);
+    writer.write_u8(state::VERSION);
+    writer.write_model(memory.model);
+    let checksum = get_cartridge_checksum(memory);
+    writer.write_bool(checksum.is_some());
+    writer.write_u16(checksum.unwrap_or_default());
+}
+
+fn read_header(reader: &mut StateReader) -> Result<StateHeader, StateError> {
+    if reader.read_bytes(state::MAGIC.len())? != state::MAGIC {
+        return Err(StateError::Invalid);
+    }
+    let version = reader.read_u8()?;
+    if version != state::VERSION {
+        return Err(StateError::UnsupportedVersion(version));
+    }
+    let model = reader.read_model()?;
+    let has_cartridge = reader.read_bool()?;
+    let checksum = reader.read_u16()?;
+    Ok(StateHeader {
+        model,
+        cartridge: has_cartridge.then_some(checksum),
+    })
+}
+
+fn get_cartridge_checksum(memory: &Memory) -> Option<u16> {
+    memory.cartridge.as_ref().map(|cartridge| cartridge.header.global_checksum)
+}
+
+impl GameBoy {
+    // Runs the emulated boot sequence, without a model it is picked from the header
+    pub fn new(cartridge: Cartridge, model: Option<Model>) -> Self {
+        let mut gameboy = Self::power_on(cartridge, model);
+        boot::boot_sequence(&mut gameboy.cpu, &mut gameboy.memory);
+        gameboy
+    }
+
+    // Starts at 0x0000 in the boot ROM dump at `boot_rom_path`, which has to match the model
+    pub fn with_boot_rom(cartridge: Cartridge, model: Option<Model>, boot_rom_path: &str) -> Result<Self, RomError> {
+        let mut gameboy = Self::power_on(cartridge, model);
+        boot::load_boot_rom(&mut gameboy.memory, boot_rom_path)?;
+        // the boot ROM sets up the stack pointer itself
+        gameboy.cpu.pc = 0x0000;
+        Ok(gameboy)
+    }
+
+    // Loads a ROM image and runs the emulated boot sequence
+    pub fn from_rom_bytes(data: Vec<u8>, model: Option<Model>) -> Result<Self, RomError> {
+        let cartridge = Cartridge::new(ROM::from_bytes(data)?, None)?;
+        Ok(Self::new(cartridge, model))
+    }
+
+    fn power_on(cartridge: Cartridge, model: Option<Model>) -> Self {
+        let mut memory = Memory {
+            model: model.unwrap_or_else(|| Model::detect(&cartridge.header)),
+            ..Default::default()
+        };
+        memory.insert_cartridge(cartridge);
+        Self { cpu: CPU::default(), memory }
+    }
+
+    pub fn model(&self) -> Model {
+        self.memory.model
+    }
+
+    // Returns the M-cycles the instruction took
+    pub fn step_instruction(&mut self) -> u8 {
+        instruction_mapper::execute_instruction(&mut self.cpu, &mut self.memory)
+    }
+
+    // Runs until the PPU has completed a frame, returns the M-cycles that took
+    pub fn run_frame(&mut self) -> u32 {
+        machine::step_frame(&mut self.cpu, &mut self.memory)
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        joypad::set_buttons(&mut self.memory, buttons);
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.memory.ppu.get_framebuffer()
+    }
+
+    // Audio samples produced since the last call. There is no sound emulation yet, so this is always empty.
+    pub fn drain_audio(&mut self) -> Vec<f32> {
+        Vec::new()
+    }
+
+    // Reads what the CPU would see without the PPU and DMA access restrictions, peeking has no effect on the machine
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.memory.read_byte(address as usize)
+    }
+
+    pub fn renderer(&self) -> Renderer {
+        self.memory.ppu.get_renderer()
+    }
+
+    // Takes effect from the next line on
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.memory.ppu.set_renderer(renderer);
+    }
+
+    // Turning the locks off lets the CPU into VRAM and OAM while the PPU uses them, for debugging
+    pub fn set_access_locks(&mut self, enabled: bool) {
+        self.memory.ppu.set_access_locks(enabled);
+    }
+
+    // Writes the cartridge RAM to its battery save, if one is attached
+    pub fn flush_battery_save(&mut self) {
+        save::flush(&mut self.memory);
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        let mut writer = StateWriter::default();
+        write_header(&mut writer, &self.memory);
+        self.cpu.write_state(&mut writer);
+        self.memory.write_state(&mut writer);
+        SaveState { data: writer.into_bytes() }
+    }
+
+    // Restores a snapshot taken with the same game on the same model, the machine is left alone if that fails
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), StateError> {
+        let mut reader = StateReader::new(&state.data);
+        let header = read_header(&mut reader)?;
+        if header.model != self.memory.model {
+            return Err(StateError::ModelMismatch {
+                state: header.model,
+                machine: self.memory.model,
+            });
+        }
+        if header.cartridge != get_cartridge_checksum(&self.memory) {
+            return Err(StateError::CartridgeMismatch);
+        }
+        // the ROM, the battery save and the frontend settings stay with this machine
+        let mut cpu = CPU::default();
+        let mut memory = self.memory.clone();
+        cpu.read_state(&mut reader)?;
+        memory.read_state(&mut reader)?;
+        reader.finish()?;
+        self.cpu = cpu;
+        self.memory = memory;
+        Ok(())
+    }
+}
@@ -0,0 +1,4 @@
+pub mod emu;
+pub mod gameboy;
+
+pub use gameboy::GameBoy;
@@ -1,30 +1,100 @@
-mod emu;
+mod cli;
 
-use emu::boot;
-use emu::cpu::CPU;
-use emu::io::display;
-use emu::memory::Memory;
-use emu::rom::ROM;
+use redox_damage::GameBoy;
+use redox_damage::emu::cartridge::Cartridge;
+#[cfg(feature = "sdl")]
+use redox_damage::emu::io::display;
+use redox_damage::emu::mapper;
+use redox_damage::emu::rom::{ROM, RomError};
+use redox_damage::emu::save;
 
 fn main() {
-    let mut cpu = CPU::default();
-    let mut memory = Memory::default();
-
-    let mut rom = ROM { data: None };
-    rom.load_rom_from_file("rom.gb");
-    if let Some(ref romdata) = rom.data {
-        memory.load_rom_data_into_bank_00(romdata);
-        memory.load_rom_data_into_bank_01(romdata);
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+
+    let rom = match ROM::load_rom_from_file(&options.rom_path, options.patch_path.as_deref()) {
+        Ok(rom) => rom,
+        Err(error) => exit_with_rom_error(error),
+    };
+    if options.command == cli::Command::Info {
+        print_info(&rom);
+        return;
+    }
+    for warning in rom.header.get_warnings() {
+        eprintln!("Warning: {}", warning);
     }
+    let mut cartridge = match Cartridge::new(rom, options.mapper) {
+        Ok(cartridge) => cartridge,
+        Err(error) => exit_with_rom_error(error),
+    };
+    save::attach_battery_save(&mut cartridge, save::get_save_path(&options.rom_path));
 
-    boot::boot_sequence(&mut memory);
+    let mut gameboy = match options.boot_rom_path {
+        Some(ref boot_rom_path) => match GameBoy::with_boot_rom(cartridge, options.model, boot_rom_path) {
+            Ok(gameboy) => gameboy,
+            Err(error) => {
+                eprintln!("Could not load boot ROM: {}", error);
+                std::process::exit(1);
+            }
+        },
+        None => GameBoy::new(cartridge, options.model),
+    };
+    if gameboy.model().is_cgb() {
+        eprintln!("Warning: CGB VRAM/WRAM banking, color palettes and double speed are not emulated yet");
+    }
+    gameboy.set_renderer(options.renderer);
+    gameboy.set_access_locks(!options.no_access_locks);
 
-    cpu.pc = 0x0100;
-    cpu.sp = 0xFFFE;
+    match options.command {
+        cli::Command::Headless => run_headless(&mut gameboy, options.frames),
+        _ => run_window(&mut gameboy),
+    }
+    gameboy.flush_battery_save();
+}
 
+#[cfg(feature = "sdl")]
+fn run_window(gameboy: &mut GameBoy) {
     // TODO: rename
     let mut display = display::Display::default();
-    // displaying a window is within an infinite loop
-    // so the instruction execution has been moved there
-    display.start_main_process(&mut cpu, &mut memory);
+    // the window keeps stepping the machine frame by frame until it is closed
+    display.start_main_process(gameboy);
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_window(_gameboy: &mut GameBoy) {
+    eprintln!("This build has no window (the sdl feature is disabled), use `redox-damage headless` instead");
+    std::process::exit(2);
+}
+
+// Runs a fixed number of frames and prints a checksum of the last one, so CI can compare it against a known good run
+fn run_headless(gameboy: &mut GameBoy, frames: u32) {
+    let mut cycles: u64 = 0;
+    for _ in 0..frames {
+        cycles += gameboy.run_frame() as u64;
+    }
+    let rgba = gameboy.framebuffer().to_rgba(gameboy.model(), false);
+    println!("Frames:            {}", frames);
+    println!("M-cycles:          {}", cycles);
+    println!("Framebuffer CRC32: {:08X}", crc32fast::hash(&rgba));
+}
+
+fn exit_with_rom_error(error: RomError) -> ! {
+    eprintln!("Could not load ROM: {}", error);
+    std::process::exit(1);
+}
+
+fn print_info(rom: &ROM) {
+    println!("{}", rom.header);
+    match mapper::detect_mapper(&rom.data, &rom.header) {
+        Some(kind) => println!("Mapper:            {}", kind.name()),
+        None => println!("Mapper:            unsupported"),
+    }
+    for warning in rom.header.get_warnings() {
+        eprintln!("Warning: {}", warning);
+    }
 }
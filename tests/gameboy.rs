@@ -0,0 +1,109 @@
+use redox_damage::GameBoy;
+use redox_damage::emu::framebuffer::{self, SCREEN_HEIGHT, SCREEN_WIDTH};
+use redox_damage::emu::model::Model;
+use redox_damage::gameboy::{SaveState, StateError};
+
+const ROM_SIZE: usize = 0x8000;
+// 154 lines of 456 dots, 4 dots per M-cycle
+const CYCLES_PER_FRAME: u32 = 17556;
+
+// 32 KiB ROM-only cart that runs `code` from 0x0100
+fn make_rom(code: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; ROM_SIZE];
+    rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+    rom[0x0134..0x0138].copy_from_slice(b"TEST");
+    rom
+}
+
+fn make_gameboy(code: &[u8]) -> GameBoy {
+    GameBoy::from_rom_bytes(make_rom(code), Some(Model::Dmg)).unwrap()
+}
+
+fn get_framebuffer_crc(gameboy: &GameBoy) -> u32 {
+    crc32fast::hash(&gameboy.framebuffer().to_rgba(gameboy.model(), false))
+}
+
+#[test]
+fn frames_take_70224_dots() {
+    // JR -2
+    let mut gameboy = make_gameboy(&[0x18, 0xFE]);
+    gameboy.run_frame();
+    let cycles: u32 = (0..10).map(|_| gameboy.run_frame()).sum();
+    // a frame only ends between instructions, JR takes 3 M-cycles
+    assert!(cycles.abs_diff(10 * CYCLES_PER_FRAME) < 3, "{} M-cycles for 10 frames", cycles);
+}
+
+#[test]
+fn framebuffer_shows_palette_changes() {
+    // LD A,0xFF; LDH (0x47),A; JR -2 maps every background color to black
+    let mut gameboy = make_gameboy(&[0x3E, 0xFF, 0xE0, 0x47, 0x18, 0xFE]);
+    gameboy.run_frame();
+    gameboy.run_frame();
+    let [r, g, b] = framebuffer::get_shade_rgb(3, Model::Dmg);
+    let expected: Vec<u8> = [r, g, b, 0xFF].repeat(SCREEN_WIDTH * SCREEN_HEIGHT);
+    assert_eq!(get_framebuffer_crc(&gameboy), crc32fast::hash(&expected));
+    assert_eq!(gameboy.read_memory(0xFF47), 0xFF);
+}
+
+#[test]
+fn runs_are_deterministic() {
+    let code = [0x3E, 0xFF, 0xE0, 0x47, 0x18, 0xFE];
+    let run = || {
+        let mut gameboy = make_gameboy(&code);
+        let cycles: u32 = (0..5).map(|_| gameboy.run_frame()).sum();
+        (cycles, get_framebuffer_crc(&gameboy))
+    };
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn save_state_restores_the_machine() {
+    let mut gameboy = make_gameboy(&[0x3C, 0x18, 0xFD]);
+    gameboy.run_frame();
+    let state = gameboy.save_state();
+    let expected: Vec<u32> = (0..3).map(|_| gameboy.run_frame()).collect();
+    gameboy.load_state(&state).unwrap();
+    let replayed: Vec<u32> = (0..3).map(|_| gameboy.run_frame()).collect();
+    assert_eq!(replayed, expected);
+}
+
+#[test]
+fn save_state_round_trips_through_bytes() {
+    let mut rom = make_rom(&[0x3C, 0xE0, 0x47, 0x18, 0xFB]);
+    // not a single ROM byte may end up in the state
+    rom[0x4000..0x4010].copy_from_slice(b"ROM ONLY MARKER!");
+    let mut gameboy = GameBoy::from_rom_bytes(rom.clone(), Some(Model::Dmg)).unwrap();
+    gameboy.run_frame();
+    let data = gameboy.save_state().as_bytes().to_vec();
+    assert!(!data.windows(16).any(|window| window == b"ROM ONLY MARKER!"));
+    let expected: Vec<(u32, u32)> = (0..3).map(|_| (gameboy.run_frame(), get_framebuffer_crc(&gameboy))).collect();
+
+    // a fresh machine with the same ROM continues exactly where the state was taken
+    let mut restored = GameBoy::from_rom_bytes(rom, Some(Model::Dmg)).unwrap();
+    restored.load_state(&SaveState::from_bytes(data.clone()).unwrap()).unwrap();
+    assert_eq!(restored.save_state().as_bytes(), data);
+    let replayed: Vec<(u32, u32)> = (0..3).map(|_| (restored.run_frame(), get_framebuffer_crc(&restored))).collect();
+    assert_eq!(replayed, expected);
+}
+
+#[test]
+fn load_state_rejects_other_machines_and_bad_data() {
+    let mut gameboy = make_gameboy(&[0x18, 0xFE]);
+    let state = gameboy.save_state();
+    assert_eq!(state.get_model(), Model::Dmg);
+
+    let mut other_rom = make_rom(&[0x18, 0xFE]);
+    other_rom[0x014E] = 0x12;
+    let mut other_game = GameBoy::from_rom_bytes(other_rom, Some(Model::Dmg)).unwrap();
+    assert!(matches!(other_game.load_state(&state), Err(StateError::CartridgeMismatch)));
+    let mut other_model = GameBoy::from_rom_bytes(make_rom(&[0x18, 0xFE]), Some(Model::Mgb)).unwrap();
+    assert!(matches!(other_model.load_state(&state), Err(StateError::ModelMismatch { .. })));
+
+    assert!(matches!(SaveState::from_bytes(b"not a state".to_vec()), Err(StateError::Invalid)));
+    let mut data = state.as_bytes().to_vec();
+    data[4] = 0xFF;
+    assert!(matches!(SaveState::from_bytes(data), Err(StateError::UnsupportedVersion(0xFF))));
+    let data = state.as_bytes();
+    let truncated = SaveState::from_bytes(data[..data.len() - 1].to_vec()).unwrap();
+    assert!(matches!(gameboy.load_state(&truncated), Err(StateError::Invalid)));
+}